@@ -0,0 +1,49 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{rejection::RawPathParamsRejection, RawPathParams, Request},
+    middleware::Next,
+    response::Response,
+};
+use log::{log, Level};
+
+/// Endpoints polled by orchestrators and scrapers, which are logged at debug level to avoid
+/// drowning out other requests.
+const PROBE_PATHS: [&str; 3] = ["/ready", "/status", "/metrics"];
+
+/// Middleware logging each request's method, path, response status and latency.
+pub async fn log_request(
+    params: Result<RawPathParams, RawPathParamsRejection>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+    let job_id = params.ok().and_then(|params| {
+        params
+            .iter()
+            .find(|(key, _)| *key == "job_id")
+            .map(|(_, value)| value.to_owned())
+    });
+
+    let started_at = Instant::now();
+    let response = next.run(request).await;
+    let elapsed = started_at.elapsed();
+
+    let level = if PROBE_PATHS.contains(&path.as_str()) {
+        Level::Debug
+    } else {
+        Level::Info
+    };
+    let status = response.status().as_u16();
+    let latency_ms = elapsed.as_secs_f64() * 1000.0;
+    match job_id {
+        Some(job_id) => log!(
+            level,
+            job_id = job_id.as_str();
+            "{} {} {} {:.1}ms", method, path, status, latency_ms
+        ),
+        None => log!(level, "{} {} {} {:.1}ms", method, path, status, latency_ms),
+    }
+    response
+}
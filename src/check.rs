@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use reqwest::StatusCode;
+
+use crate::{
+    executors::check_docker_connection,
+    settings::{Settings, SETTINGS},
+    USER_AGENT,
+};
+
+/// Run foreman's pre-flight checks, printing the outcome of each.
+/// Returns an error if any check fails.
+pub async fn run() -> Result<()> {
+    // Load settings directly rather than via `SETTINGS`, which panics on invalid settings
+    if let Err(e) = Settings::new() {
+        println!("[FAIL] settings: {}", e);
+        bail!("Pre-flight checks failed");
+    }
+    println!("[ OK ] settings");
+    let settings = &*SETTINGS;
+
+    let mut failed = false;
+    let mut report = |name: &str, result: Result<()>| match result {
+        Ok(()) => println!("[ OK ] {}", name),
+        Err(e) => {
+            println!("[FAIL] {}: {}", name, e);
+            failed = true;
+        }
+    };
+
+    report("port", check_port(settings.core.port));
+    report("docker", check_docker_connection().await);
+    for url in &settings.core.url {
+        report(
+            &format!("control server {}", url),
+            check_control_server(
+                url,
+                &settings.core.token,
+                Duration::from_millis(settings.core.poll_timeout.into()),
+            )
+            .await,
+        );
+    }
+
+    if failed {
+        bail!("Pre-flight checks failed");
+    }
+    Ok(())
+}
+
+fn check_port(port: u16) -> Result<()> {
+    if port == 0 {
+        bail!("core.port must be between 1 and 65535");
+    }
+    Ok(())
+}
+
+/// Check the control server is reachable and accepts our token. A `HEAD` request is sent, rather
+/// than polling, so no jobs are handed out.
+async fn check_control_server(url: &str, token: &str, timeout: Duration) -> Result<()> {
+    let resp = reqwest::Client::builder()
+        .user_agent(&*USER_AGENT)
+        .build()?
+        .head(url)
+        .timeout(timeout)
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await?;
+    match resp.status() {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            bail!("token rejected with status {}", resp.status())
+        }
+        status if status.is_server_error() => bail!("responded with status {}", status),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_port() {
+        assert!(check_port(0).is_err());
+        assert!(check_port(3000).is_ok());
+    }
+}
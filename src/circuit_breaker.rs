@@ -0,0 +1,79 @@
+use std::time::{Duration, Instant};
+
+/// A simple circuit breaker which opens after `threshold` consecutive failures, remaining open
+/// for `cooldown` before another attempt should be made.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    /// Consecutive failures required to open the circuit. Zero disables the circuit breaker.
+    threshold: u32,
+    cooldown: Duration,
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            threshold,
+            cooldown,
+            consecutive_failures: 0,
+            open_until: None,
+        }
+    }
+
+    /// When the circuit is open, the time at which the cooldown ends and another attempt
+    /// should be made.
+    pub fn open_until(&self) -> Option<Instant> {
+        self.open_until
+    }
+
+    /// Record a successful operation, closing the circuit.
+    pub fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+        self.open_until = None;
+    }
+
+    /// Record a failed operation. Returns `true` if the circuit is now open, in which case a
+    /// further failure (e.g. of an attempt made after the cooldown) re-opens it.
+    pub fn record_failure(&mut self) -> bool {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        if self.threshold == 0 || self.consecutive_failures < self.threshold {
+            return false;
+        }
+        self.open_until = Some(Instant::now() + self.cooldown);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_after_threshold_consecutive_failures() {
+        let mut circuit_breaker = CircuitBreaker::new(3, Duration::from_secs(30));
+        assert!(!circuit_breaker.record_failure());
+        assert!(!circuit_breaker.record_failure());
+        circuit_breaker.record_success();
+        assert!(!circuit_breaker.record_failure());
+        assert!(!circuit_breaker.record_failure());
+        assert!(circuit_breaker.open_until().is_none());
+
+        assert!(circuit_breaker.record_failure());
+        assert!(circuit_breaker.open_until().unwrap() > Instant::now());
+        // A failed attempt after the cooldown re-opens the circuit
+        assert!(circuit_breaker.record_failure());
+
+        circuit_breaker.record_success();
+        assert!(circuit_breaker.open_until().is_none());
+    }
+
+    #[test]
+    fn test_zero_threshold_never_opens() {
+        let mut circuit_breaker = CircuitBreaker::new(0, Duration::from_secs(30));
+        for _ in 0..10 {
+            assert!(!circuit_breaker.record_failure());
+        }
+        assert!(circuit_breaker.open_until().is_none());
+    }
+}
@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+
+pub const USAGE: &str = "Usage: foreman [OPTIONS] [COMMAND]
+
+Commands:
+  check          Run pre-flight checks and exit
+  config         Print the resolved settings as JSON and exit
+
+Options:
+  --config PATH  Path to foreman.toml, takes precedence over FOREMAN_CONFIG
+  -h, --help     Print this message and exit
+  -V, --version  Print the version and exit";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Check,
+    Config,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    pub config: Option<PathBuf>,
+    pub command: Option<Command>,
+    pub help: bool,
+    pub version: bool,
+}
+
+impl Args {
+    /// Parse command line arguments, excluding the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => match args.next() {
+                    Some(path) => parsed.config = Some(PathBuf::from(path)),
+                    None => bail!("--config requires a path"),
+                },
+                "-h" | "--help" => parsed.help = true,
+                "-V" | "--version" => parsed.version = true,
+                "check" if parsed.command.is_none() => parsed.command = Some(Command::Check),
+                "config" if parsed.command.is_none() => parsed.command = Some(Command::Config),
+                _ => match arg.strip_prefix("--config=") {
+                    Some(path) => parsed.config = Some(PathBuf::from(path)),
+                    None => bail!("Unexpected argument '{}'\n\n{}", arg, USAGE),
+                },
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        Args::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(&[]).unwrap(), Args::default());
+        assert_eq!(
+            parse(&["--config", "/etc/foreman.toml", "check"]).unwrap(),
+            Args {
+                config: Some(PathBuf::from("/etc/foreman.toml")),
+                command: Some(Command::Check),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            parse(&["--config=foreman.toml"]).unwrap().config,
+            Some(PathBuf::from("foreman.toml"))
+        );
+        assert_eq!(parse(&["config"]).unwrap().command, Some(Command::Config));
+        assert!(parse(&["--version"]).unwrap().version);
+        assert!(parse(&["-h"]).unwrap().help);
+        assert!(parse(&["--config"]).is_err());
+        assert!(parse(&["bogus"]).is_err());
+    }
+}
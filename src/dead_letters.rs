@@ -0,0 +1,173 @@
+use std::{collections::VecDeque, path::PathBuf, sync::Mutex, time::SystemTime};
+
+use anyhow::Result;
+use serde::Serialize;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+use crate::{
+    job::Job,
+    tracking::{FailureReason, JobStatus, TrackedJob},
+};
+
+/// Record of a job which failed terminally. The job's body and environment are omitted, as they
+/// may contain secrets.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetter {
+    pub job_id: String,
+    pub image: String,
+    pub trace_id: Option<String>,
+    pub status: JobStatus,
+    pub reason: Option<FailureReason>,
+    pub error: String,
+    pub exit_code: Option<i64>,
+    pub result: Option<String>,
+    /// RFC3339 timestamp of when the job was received.
+    pub received_at: String,
+    /// RFC3339 timestamp of when the job's container started, if it did.
+    pub container_started_at: Option<String>,
+    /// RFC3339 timestamp of when the job failed.
+    pub failed_at: String,
+}
+
+fn format_time(time: SystemTime) -> String {
+    OffsetDateTime::from(time)
+        .format(&Rfc3339)
+        .unwrap_or_default()
+}
+
+impl DeadLetter {
+    pub fn new(tracked_job: &TrackedJob, error: &str) -> Self {
+        let Job::Docker(docker_job) = tracked_job.inner();
+        DeadLetter {
+            job_id: docker_job.id.clone(),
+            image: docker_job.image.clone(),
+            trace_id: docker_job.trace_id.clone(),
+            status: tracked_job.status().clone(),
+            reason: tracked_job.failure_reason(),
+            error: error.to_owned(),
+            exit_code: tracked_job.exit_code(),
+            result: tracked_job.result().map(str::to_owned),
+            received_at: format_time(tracked_job.start_time()),
+            container_started_at: tracked_job.container_started_time().map(format_time),
+            failed_at: format_time(SystemTime::now()),
+        }
+    }
+}
+
+/// Keeps the most recent dead letters in memory, optionally appending every dead letter to a
+/// file as newline delimited JSON.
+pub struct DeadLetters {
+    letters: Mutex<VecDeque<DeadLetter>>,
+    capacity: usize,
+    file: Option<PathBuf>,
+    /// Serializes writes so concurrent dead letters aren't interleaved.
+    file_lock: tokio::sync::Mutex<()>,
+}
+
+impl DeadLetters {
+    pub fn new(capacity: usize, file: Option<PathBuf>) -> Self {
+        DeadLetters {
+            letters: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            file,
+            file_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Record a dead letter, evicting the oldest if at capacity. The dead letter is kept in memory
+    /// even if appending it to the file fails.
+    pub async fn record(&self, letter: DeadLetter) -> Result<()> {
+        let mut line = serde_json::to_string(&letter)?;
+        line.push('\n');
+        {
+            let mut letters = self.letters.lock().unwrap();
+            if letters.len() >= self.capacity {
+                letters.pop_front();
+            }
+            if self.capacity > 0 {
+                letters.push_back(letter);
+            }
+        }
+
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let _guard = self.file_lock.lock().await;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// Returns the dead letters held in memory, oldest first.
+    pub fn list(&self) -> Vec<DeadLetter> {
+        self.letters.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracking::JobTracker;
+    use tracing::Span;
+
+    fn dead_letter(job_tracker: &mut JobTracker, id: &str) -> DeadLetter {
+        let job = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "image": "alpine:latest",
+            "body": null,
+            "callbackUrl": "https://api.example.com/callback"
+        }))
+        .unwrap();
+        job_tracker.insert(job, Span::none()).unwrap();
+        job_tracker
+            .set_failure_reason(id, FailureReason::PrepareFailed)
+            .unwrap();
+        job_tracker
+            .update_status(id, JobStatus::Failed, None)
+            .unwrap();
+        let tracked_job = job_tracker.get_job(id).unwrap().lock().unwrap();
+        DeadLetter::new(&tracked_job, "failed to pull image")
+    }
+
+    #[tokio::test]
+    async fn test_record_evicts_oldest_and_appends_to_file() {
+        let path = std::env::temp_dir().join(format!(
+            "foreman-dead-letters-{}.ndjson",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let dead_letters = DeadLetters::new(2, Some(path.clone()));
+        let mut job_tracker = JobTracker::new();
+        for id in ["a", "b", "c"] {
+            let letter = dead_letter(&mut job_tracker, id);
+            dead_letters.record(letter).await.unwrap();
+        }
+
+        let ids: Vec<String> = dead_letters
+            .list()
+            .into_iter()
+            .map(|letter| letter.job_id)
+            .collect();
+        assert_eq!(ids, vec!["b", "c"]);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["job_id"], "a");
+        assert_eq!(lines[0]["status"], "FAILED");
+        assert_eq!(lines[0]["reason"], "prepare_failed");
+        assert_eq!(lines[0]["error"], "failed to pull image");
+        assert_eq!(lines[0]["image"], "alpine:latest");
+        assert!(lines[0]["container_started_at"].is_null());
+    }
+}
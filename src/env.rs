@@ -1,8 +1,9 @@
 use std::collections::HashMap;
 
-use serde::Deserialize;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, PartialEq, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Default)]
 pub struct EnvVars(HashMap<String, String>);
 
 impl EnvVars {
@@ -31,15 +32,117 @@ impl EnvVars {
         new_map.extend(other.0.iter().map(|(k, v)| (k.clone(), v.clone())));
         EnvVars(new_map)
     }
+
+    /// Check every variable name is valid i.e., non-empty, does not contain `=` or a NUL
+    /// character and does not start with a digit.
+    pub fn validate(&self) -> Result<()> {
+        let mut names: Vec<&String> = self.0.keys().collect();
+        names.sort();
+        for name in names {
+            if name.is_empty() {
+                bail!("Invalid environment variable name, must not be empty");
+            }
+            if name.contains('=') || name.contains('\0') {
+                bail!(
+                    "Invalid environment variable name '{}', must not contain '=' or NUL",
+                    name
+                );
+            }
+            if name.starts_with(|c: char| c.is_ascii_digit()) {
+                bail!(
+                    "Invalid environment variable name '{}', must not start with a digit",
+                    name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Merge the given protected EnvVars into this one. Protected variables always override
+    /// existing keys. Returns the sorted keys which collided, i.e. were overridden.
+    pub fn merge_protected(&mut self, protected: EnvVars) -> Vec<String> {
+        let mut collisions: Vec<String> = protected
+            .0
+            .keys()
+            .filter(|k| self.0.contains_key(*k))
+            .cloned()
+            .collect();
+        collisions.sort();
+        self.merge(protected);
+        collisions
+    }
 }
 
 impl From<EnvVars> for Vec<String> {
-    /// Convert EnvVars to Vec<String> where each string is formatted as "Key=Value"
+    /// Convert EnvVars to Vec<String> where each string is formatted as "Key=Value".
+    /// The result is sorted by key so the order is deterministic.
     fn from(env_vars: EnvVars) -> Self {
-        env_vars
-            .inner()
-            .iter()
+        let mut pairs: Vec<(String, String)> = env_vars.0.into_iter().collect();
+        pairs.sort();
+        pairs
+            .into_iter()
             .map(|(k, v)| format!("{}={}", k, v))
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_vars(pairs: &[(&str, &str)]) -> EnvVars {
+        EnvVars(
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_into_vec_is_sorted() {
+        let env_strings: Vec<String> = env_vars(&[("C", "3"), ("A", "1"), ("B", "2")]).into();
+        assert_eq!(env_strings, vec!["A=1", "B=2", "C=3"]);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(env_vars(&[("FOO", "1"), ("_BAR2", "2"), ("lower", "")])
+            .validate()
+            .is_ok());
+        for name in ["", "A=B", "1FOO", "NUL\0"] {
+            let err = env_vars(&[("OK", "1"), (name, "1")])
+                .validate()
+                .unwrap_err();
+            assert!(err
+                .to_string()
+                .contains("Invalid environment variable name"));
+        }
+        assert!(env_vars(&[("1FOO", "1")])
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("'1FOO'"));
+    }
+
+    #[test]
+    fn test_merge_protected() {
+        let mut env = env_vars(&[("A", "1"), ("FOREMAN_Y", "user"), ("FOREMAN_X", "user")]);
+        let collisions = env.merge_protected(env_vars(&[
+            ("FOREMAN_X", "x"),
+            ("FOREMAN_Y", "y"),
+            ("FOREMAN_Z", "z"),
+        ]));
+
+        assert_eq!(collisions, vec!["FOREMAN_X", "FOREMAN_Y"]);
+        assert_eq!(
+            env,
+            env_vars(&[
+                ("A", "1"),
+                ("FOREMAN_X", "x"),
+                ("FOREMAN_Y", "y"),
+                ("FOREMAN_Z", "z")
+            ])
+        );
+    }
+}
@@ -1,55 +1,564 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    env,
+    net::{IpAddr, Ipv6Addr},
+    path::Path,
+    time::Duration,
+};
 
 use crate::{
     env::EnvVars,
-    job::{DockerJob, Job},
+    job::{
+        parse_size, validate_callback_url, validate_job_id, validate_ulimits, DockerJob, Gpus, Job,
+        RestartPolicy, Ulimits,
+    },
     settings::SETTINGS,
 };
 use futures::{future, stream::StreamExt};
-use log::info;
+use log::{debug, info, warn};
 
-use super::JobExecutor;
+use super::{ContainerExit, JobExecutor, JobStats};
 
 use anyhow::{bail, Result};
 use bollard::{
-    container::{Config, CreateContainerOptions, StartContainerOptions, StopContainerOptions},
+    container::{
+        Config, CreateContainerOptions, KillContainerOptions, MemoryStatsStats,
+        RemoveContainerOptions, StartContainerOptions, Stats, StatsOptions, StopContainerOptions,
+    },
     image::{CreateImageOptions, ListImagesOptions},
-    network::CreateNetworkOptions,
-    secret::{ContainerCreateResponse, ContainerInspectResponse},
-    Docker,
+    network::{ConnectNetworkOptions, CreateNetworkOptions},
+    secret::{
+        ContainerCreateResponse, ContainerInspectResponse, ContainerStateStatusEnum, DeviceRequest,
+        EndpointSettings, HostConfigLogConfig, Ipam, IpamConfig, ResourcesUlimits,
+        RestartPolicyNameEnum,
+    },
+    ClientVersion, Docker, API_DEFAULT_VERSION,
 };
 
-#[derive(Debug)]
+/// Whether a Docker API error is a 404 i.e., the container (or other object) does not exist.
+fn is_not_found(e: &bollard::errors::Error) -> bool {
+    has_status_code(e, 404)
+}
+
+/// Whether a Docker API error is a 304 e.g., the container is already stopped.
+fn is_not_modified(e: &bollard::errors::Error) -> bool {
+    has_status_code(e, 304)
+}
+
+fn has_status_code(e: &bollard::errors::Error, code: u16) -> bool {
+    matches!(
+        e,
+        bollard::errors::Error::DockerResponseServerError { status_code, .. }
+            if *status_code == code
+    )
+}
+
+/// Whether a Docker API error may succeed if retried i.e., it isn't a client error such as a
+/// missing container or invalid configuration.
+fn is_transient(e: &bollard::errors::Error) -> bool {
+    !matches!(
+        e,
+        bollard::errors::Error::DockerResponseServerError { status_code, .. } if *status_code < 500
+    )
+}
+
+/// Call `f` until it succeeds, up to `attempts` times, doubling the delay between attempts from
+/// `backoff`. Errors which aren't transient are returned immediately.
+async fn retry<T, F, Fut>(attempts: u32, backoff: Duration, mut f: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: future::Future<Output = Result<T, bollard::errors::Error>>,
+{
+    let mut backoff = backoff;
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < attempts && is_transient(&e) => {
+                warn!(
+                    "Docker operation failed (attempt {} of {}), retrying in {}ms: {}",
+                    attempt,
+                    attempts,
+                    backoff.as_millis(),
+                    e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Calculate CPU usage as a percentage of a single CPU from two consecutive samples of a
+/// container's total CPU usage and the host's system CPU usage, as `docker stats` does.
+fn cpu_percent(
+    total_usage: u64,
+    pre_total_usage: u64,
+    system_usage: u64,
+    pre_system_usage: u64,
+    online_cpus: u64,
+) -> f64 {
+    let cpu_delta = total_usage.saturating_sub(pre_total_usage) as f64;
+    let system_delta = system_usage.saturating_sub(pre_system_usage) as f64;
+    if cpu_delta <= 0.0 || system_delta <= 0.0 {
+        return 0.0;
+    }
+    (cpu_delta / system_delta) * online_cpus as f64 * 100.0
+}
+
+impl From<&Stats> for JobStats {
+    fn from(stats: &Stats) -> Self {
+        let Stats {
+            cpu_stats,
+            precpu_stats,
+            memory_stats,
+            pids_stats,
+            ..
+        } = stats;
+        let online_cpus = cpu_stats
+            .online_cpus
+            .or_else(|| {
+                cpu_stats
+                    .cpu_usage
+                    .percpu_usage
+                    .as_ref()
+                    .map(|usage| usage.len() as u64)
+            })
+            .unwrap_or(1);
+        // Exclude the page cache from memory usage, as `docker stats` does
+        let cache = match memory_stats.stats {
+            Some(MemoryStatsStats::V1(stats)) => stats.total_inactive_file,
+            Some(MemoryStatsStats::V2(stats)) => stats.inactive_file,
+            None => 0,
+        };
+        JobStats {
+            cpu_percent: cpu_percent(
+                cpu_stats.cpu_usage.total_usage,
+                precpu_stats.cpu_usage.total_usage,
+                cpu_stats.system_cpu_usage.unwrap_or_default(),
+                precpu_stats.system_cpu_usage.unwrap_or_default(),
+                online_cpus,
+            ),
+            memory_usage_bytes: memory_stats.usage.unwrap_or_default().saturating_sub(cache),
+            memory_limit_bytes: memory_stats.limit,
+            pids: pids_stats.current,
+        }
+    }
+}
+
+/// URL of a job's endpoint on foreman's API, as reached from the job's container.
+fn job_endpoint(scheme: &str, hostname: &str, port: u16, job_id: &str) -> String {
+    // IPv6 literals must be bracketed to be distinguished from the port
+    if hostname.parse::<Ipv6Addr>().is_ok() {
+        format!("{}://[{}]:{}/job/{}", scheme, hostname, port, job_id)
+    } else {
+        format!("{}://{}:{}/job/{}", scheme, hostname, port, job_id)
+    }
+}
+
+/// Convert a duration in milliseconds to whole seconds, as expected by the Docker API.
+fn millis_to_secs(millis: u64) -> i64 {
+    (millis / 1000) as i64
+}
+
+/// Split an extra host entry in the format `host:ip` into it's host and ip parts.
+/// The special value `host-gateway` is accepted in place of an ip.
+fn parse_extra_host(entry: &str) -> Result<(&str, &str)> {
+    let Some((host, ip)) = entry.split_once(':') else {
+        bail!("Invalid extra host '{}', expected format 'host:ip'", entry);
+    };
+    if host.is_empty() {
+        bail!("Invalid extra host '{}', host must not be empty", entry);
+    }
+    if ip != "host-gateway" && ip.parse::<IpAddr>().is_err() {
+        bail!("Invalid extra host '{}', '{}' is not a valid ip", entry, ip);
+    }
+    Ok((host, ip))
+}
+
+/// Check each DNS server is a valid IP address.
+fn validate_dns(servers: &[String]) -> Result<()> {
+    for server in servers {
+        if server.parse::<IpAddr>().is_err() {
+            bail!("Invalid DNS server '{}', expected an ip address", server);
+        }
+    }
+    Ok(())
+}
+
+/// Check a cpuset is a comma separated list of CPU numbers and/or inclusive ranges of CPU numbers,
+/// the format Docker expects e.g. `0-3` or `0,2`.
+fn validate_cpuset(cpuset: &str) -> Result<()> {
+    let valid_item = |item: &str| match item.split_once('-') {
+        Some((start, end)) => match (start.parse::<u32>(), end.parse::<u32>()) {
+            (Ok(start), Ok(end)) => start <= end,
+            _ => false,
+        },
+        None => item.parse::<u32>().is_ok(),
+    };
+    if !cpuset.split(',').all(valid_item) {
+        bail!(
+            "Invalid cpuset '{}', expected CPU numbers and/or ranges e.g. 0-3 or 0,2",
+            cpuset
+        );
+    }
+    Ok(())
+}
+
+/// Interval between the two stats samples a container's CPU usage is calculated from.
+const STATS_SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Exit code of a container's process when killed with SIGKILL (128 + 9).
+const SIGKILL_EXIT_CODE: i64 = 137;
+
+/// Maximum length of a container's hostname, as limited by Linux.
+const MAX_HOSTNAME_LEN: usize = 64;
+
+/// Check a hostname is made up of dot separated labels of ASCII alphanumeric characters and `-`,
+/// with no label starting or ending with `-`.
+fn validate_hostname(hostname: &str) -> Result<()> {
+    if hostname.len() > MAX_HOSTNAME_LEN {
+        bail!(
+            "Invalid hostname '{}', must be at most {} characters",
+            hostname,
+            MAX_HOSTNAME_LEN
+        );
+    }
+    let valid_label = |label: &str| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    if !hostname.split('.').all(valid_label) {
+        bail!("Invalid hostname '{}'", hostname);
+    }
+    Ok(())
+}
+
+/// Hostname for a job's container. Defaults to the container's name so it's predictable, unless
+/// the name isn't a valid hostname e.g. it's too long, in which case Docker generates one.
+fn resolve_hostname<'a>(
+    hostname: Option<&'a str>,
+    container_name: &'a str,
+) -> Result<Option<&'a str>> {
+    match hostname {
+        Some(hostname) => {
+            validate_hostname(hostname)?;
+            Ok(Some(hostname))
+        }
+        None => Ok(validate_hostname(container_name)
+            .is_ok()
+            .then_some(container_name)),
+    }
+}
+
+/// Parse a human-friendly `/dev/shm` size e.g. `1g` into bytes.
+fn resolve_shm_size(shm_size: Option<&str>) -> Result<Option<i64>> {
+    let Some(shm_size) = shm_size else {
+        return Ok(None);
+    };
+    let bytes = parse_size(shm_size)?;
+    if bytes == 0 {
+        bail!("Invalid shm size '{}', must be greater than 0", shm_size);
+    }
+    Ok(Some(i64::try_from(bytes)?))
+}
+
+/// Merge the global and job ulimits, with job ulimits overriding global ulimits of the same name.
+/// Returns an error if any ulimit is unknown or invalid.
+fn resolve_ulimits(
+    global: Option<&Ulimits>,
+    job: Option<&Ulimits>,
+) -> Result<Option<Vec<ResourcesUlimits>>> {
+    if global.is_none() && job.is_none() {
+        return Ok(None);
+    }
+    let mut resolved = Ulimits::new();
+    for ulimits in global.into_iter().chain(job) {
+        validate_ulimits(ulimits)?;
+        resolved.extend(ulimits.iter().map(|(name, ulimit)| (name.clone(), *ulimit)));
+    }
+    let mut resolved: Vec<ResourcesUlimits> = resolved
+        .into_iter()
+        .map(|(name, ulimit)| ResourcesUlimits {
+            name: Some(name),
+            soft: Some(ulimit.soft),
+            hard: Some(ulimit.hard),
+        })
+        .collect();
+    resolved.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Some(resolved))
+}
+
+/// Merge the global and job extra hosts, with job entries overriding global entries for the
+/// same host. Returns an error if any entry is malformed.
+fn resolve_extra_hosts(
+    global: Option<&Vec<String>>,
+    job: Option<&Vec<String>>,
+) -> Result<Option<Vec<String>>> {
+    if global.is_none() && job.is_none() {
+        return Ok(None);
+    }
+    let mut resolved: Vec<(&str, &str)> = Vec::new();
+    for entry in global.into_iter().chain(job).flatten() {
+        let (host, ip) = parse_extra_host(entry)?;
+        match resolved.iter_mut().find(|(h, _)| *h == host) {
+            Some(existing) => existing.1 = ip,
+            None => resolved.push((host, ip)),
+        }
+    }
+    Ok(Some(
+        resolved
+            .into_iter()
+            .map(|(host, ip)| format!("{}:{}", host, ip))
+            .collect(),
+    ))
+}
+
+/// Merge a container's environment variables with the following precedence, from lowest to
+/// highest: the `core.env` defaults, the job's `env`, then the variables injected by foreman.
+/// Foreman's injected variables can't be overridden, attempts to do so are logged and ignored.
+fn resolve_env(
+    default_env: Option<&EnvVars>,
+    job_env: Option<&EnvVars>,
+    injected_env: EnvVars,
+) -> EnvVars {
+    let mut resolved = default_env.cloned().unwrap_or_default();
+    if let Some(job_env) = job_env {
+        resolved.merge(job_env.clone());
+    }
+    for key in resolved.merge_protected(injected_env) {
+        warn!(
+            "Ignoring environment variable {} as it is reserved by foreman",
+            key
+        );
+    }
+    resolved
+}
+
+impl From<&RestartPolicy> for bollard::service::RestartPolicy {
+    fn from(restart_policy: &RestartPolicy) -> Self {
+        let (name, maximum_retry_count) = match restart_policy {
+            RestartPolicy::No => (RestartPolicyNameEnum::NO, None),
+            RestartPolicy::OnFailure(max_retries) => (
+                RestartPolicyNameEnum::ON_FAILURE,
+                max_retries.map(|n| n as i64),
+            ),
+            RestartPolicy::Always => (RestartPolicyNameEnum::ALWAYS, None),
+            RestartPolicy::UnlessStopped => (RestartPolicyNameEnum::UNLESS_STOPPED, None),
+        };
+        bollard::service::RestartPolicy {
+            name: Some(name),
+            maximum_retry_count,
+        }
+    }
+}
+
+impl From<&Gpus> for DeviceRequest {
+    fn from(gpus: &Gpus) -> Self {
+        let (count, device_ids) = match gpus {
+            // -1 requests all available GPUs
+            Gpus::All => (Some(-1), None),
+            Gpus::Count(count) => (Some(*count as i64), None),
+            Gpus::DeviceIds(ids) => (None, Some(ids.clone())),
+        };
+        DeviceRequest {
+            driver: Some("nvidia".to_string()),
+            count,
+            device_ids,
+            capabilities: Some(vec![vec!["gpu".to_string()]]),
+            options: None,
+        }
+    }
+}
+
+/// Timeout in seconds for requests to the Docker daemon.
+const DOCKER_TIMEOUT: u64 = 120;
+
+/// Connect to the Docker daemon at `url`, or the local defaults if `url` is `None`. When an
+/// `api_version` is pinned without a `url`, the `DOCKER_HOST` environment variable (falling back
+/// to the local Docker socket) is resolved first so the pinned version is applied to it.
+///
+/// `unix://` and `npipe://` URLs connect over a socket/named pipe. `tcp://` and `http://` URLs
+/// connect over plain HTTP unless TLS files are given, in which case (and for `https://` URLs)
+/// the connection is made over TLS.
+fn connect(
+    url: Option<&str>,
+    tls: Option<(&str, &str, &str)>,
+    api_version: Option<&ClientVersion>,
+) -> Result<Docker> {
+    let docker_host;
+    let url = match (url, api_version) {
+        (Some(url), _) => url,
+        (None, Some(_)) => {
+            docker_host = env::var("DOCKER_HOST").unwrap_or(DEFAULT_DOCKER_SOCKET.to_string());
+            &docker_host
+        }
+        (None, None) => return Ok(Docker::connect_with_local_defaults()?),
+    };
+    let api_version = api_version.unwrap_or(API_DEFAULT_VERSION);
+    let docker = match url.split_once("://").map(|(scheme, _)| scheme) {
+        Some("unix") | Some("npipe") => {
+            Docker::connect_with_socket(url, DOCKER_TIMEOUT, api_version)?
+        }
+        Some("tcp") | Some("http") | Some("https") => match tls {
+            Some((ca, cert, key)) => {
+                // bollard expects a tcp:// or https:// address when connecting over TLS
+                let url = url.replacen("http://", "https://", 1);
+                Docker::connect_with_ssl(
+                    &url,
+                    Path::new(key),
+                    Path::new(cert),
+                    Path::new(ca),
+                    DOCKER_TIMEOUT,
+                    api_version,
+                )?
+            }
+            None if url.starts_with("https://") => {
+                bail!(
+                    "docker.tls_ca, docker.tls_cert and docker.tls_key must be set to \
+                     connect to {}",
+                    url
+                )
+            }
+            None => Docker::connect_with_http(url, DOCKER_TIMEOUT, api_version)?,
+        },
+        _ => bail!("Unsupported Docker url {}", url),
+    };
+    Ok(docker)
+}
+
+/// The local Docker socket, as used by `Docker::connect_with_local_defaults`.
+#[cfg(unix)]
+const DEFAULT_DOCKER_SOCKET: &str = "unix:///var/run/docker.sock";
+#[cfg(windows)]
+const DEFAULT_DOCKER_SOCKET: &str = "npipe:////./pipe/docker_engine";
+
+/// Parse a Docker API version in the format `major.minor` e.g., `1.41`.
+fn parse_api_version(s: &str) -> Result<ClientVersion> {
+    let parsed = s.trim().split_once('.').and_then(|(major, minor)| {
+        Some(ClientVersion {
+            major_version: major.parse().ok()?,
+            minor_version: minor.parse().ok()?,
+        })
+    });
+    match parsed {
+        Some(client_version) => Ok(client_version),
+        None => bail!("Invalid Docker API version '{}', expected 'major.minor'", s),
+    }
+}
+
+/// Check the daemon supports the given API version, returning a descriptive error if not.
+async fn check_api_version_supported(docker: &Docker, api_version: &ClientVersion) -> Result<()> {
+    let version = docker.version().await?;
+    let (Some(max), Some(min)) = (version.api_version, version.min_api_version) else {
+        bail!("Docker daemon did not report it's supported API versions");
+    };
+    let (max_version, min_version) = (parse_api_version(&max)?, parse_api_version(&min)?);
+    if *api_version > max_version || *api_version < min_version {
+        bail!(
+            "Docker API version {} is not supported by the daemon (supports {} to {})",
+            api_version,
+            min,
+            max
+        );
+    }
+    Ok(())
+}
+
+/// Connect to the Docker daemon configured by the `[docker]` settings.
+async fn connect_from_settings() -> Result<Docker> {
+    let docker_settings = &SETTINGS.docker;
+    let tls = match (
+        &docker_settings.tls_ca,
+        &docker_settings.tls_cert,
+        &docker_settings.tls_key,
+    ) {
+        (Some(ca), Some(cert), Some(key)) => Some((ca.as_str(), cert.as_str(), key.as_str())),
+        (None, None, None) => None,
+        _ => bail!("docker.tls_ca, docker.tls_cert and docker.tls_key must all be set to use TLS"),
+    };
+    let api_version = docker_settings
+        .api_version
+        .as_deref()
+        .map(parse_api_version)
+        .transpose()?;
+    let docker = connect(docker_settings.url.as_deref(), tls, api_version.as_ref())?;
+    if let Some(api_version) = &api_version {
+        check_api_version_supported(&docker, api_version).await?;
+    }
+    Ok(docker)
+}
+
+/// Verify the Docker daemon configured by the `[docker]` settings is reachable.
+pub async fn check_docker_connection() -> Result<()> {
+    let docker = connect_from_settings().await?;
+    docker.ping().await?;
+    Ok(())
+}
+
+/// Cloning a `DockerExecutor` is cheap, clones share the same connection to the Docker daemon.
+#[derive(Debug, Clone)]
 pub struct DockerExecutor {
     docker: Docker,
 }
 
 impl DockerExecutor {
     pub async fn new() -> Result<Self> {
-        let docker = Docker::connect_with_local_defaults()?;
-
+        let docker = connect_from_settings().await?;
         let _self = DockerExecutor { docker };
         _self.create_network().await?;
         Ok(_self)
     }
 
-    async fn pull(&self, image: &str) -> Result<()> {
-        // println!("Pulling image {}", image);
-        info!("Pulling image {}", image);
+    /// Returns a single stats sample for the container, or `None` if it no longer exists.
+    async fn stats_sample(&self, container_name: &str) -> Result<Option<Stats>> {
+        let options = Some(StatsOptions {
+            stream: false,
+            one_shot: true,
+        });
+        match self.docker.stats(container_name, options).next().await {
+            Some(Ok(stats)) => Ok(Some(stats)),
+            Some(Err(e)) if is_not_found(&e) => Ok(None),
+            Some(Err(e)) => Err(e.into()),
+            None => bail!("No stats returned for container {}", container_name),
+        }
+    }
+
+    /// Check the Docker daemon is responding.
+    pub async fn ping(&self) -> Result<()> {
+        self.docker.ping().await?;
+        Ok(())
+    }
+
+    async fn pull(&self, job_id: &str, image: &str) -> Result<()> {
+        info!(job_id = job_id; "Pulling image {}", image);
+        tracing::info!(image, "pulling image");
 
         let options = Some(CreateImageOptions {
             from_image: image,
             ..Default::default()
         });
-        self.docker
-            .create_image(options, None, None)
-            .for_each(|p| {
-                if let Ok(info) = p {
-                    println!("{:?}", info);
-                }
-                future::ready(())
-            })
-            .await;
+        let pull = self.docker.create_image(options, None, None).for_each(|p| {
+            if let Ok(info) = p {
+                debug!(job_id = job_id; "Image pull progress: {:?}", info);
+            }
+            future::ready(())
+        });
+        // Dropping the stream on timeout closes the connection to the Docker daemon, so no
+        // container is created for the job
+        let pull_timeout = Duration::from_millis(SETTINGS.core.pull_timeout);
+        if tokio::time::timeout(pull_timeout, pull).await.is_err() {
+            bail!(
+                "Timed out pulling image {} after {}ms",
+                image,
+                SETTINGS.core.pull_timeout
+            );
+        }
         Ok(())
     }
 
@@ -61,11 +570,36 @@ impl DockerExecutor {
         let network_exists = networks
             .iter()
             .any(|n| n.name == Some(network_name.to_string()));
+        // External networks are managed elsewhere, so must already exist
+        if SETTINGS.core.network_external {
+            if !network_exists {
+                bail!(
+                    "External network {} does not exist (core.network_external is set, so \
+                     foreman won't create it)",
+                    network_name
+                );
+            }
+            info!("Using external network: {}", network_name);
+            return Ok(());
+        }
         if !network_exists {
+            let core = &SETTINGS.core;
+            // Let Docker pick the subnet unless one is configured
+            let ipam = Ipam {
+                config: core.network_subnet.as_ref().map(|subnet| {
+                    vec![IpamConfig {
+                        subnet: Some(subnet.clone()),
+                        gateway: core.network_gateway.map(|gateway| gateway.to_string()),
+                        ..Default::default()
+                    }]
+                }),
+                ..Default::default()
+            };
             let network_config = CreateNetworkOptions::<&str> {
                 name: network_name,
-                driver: "bridge",
-                enable_ipv6: false,
+                driver: &core.network_driver,
+                enable_ipv6: core.network_enable_ipv6,
+                ipam,
                 ..Default::default()
             };
 
@@ -75,83 +609,295 @@ impl DockerExecutor {
         Ok(())
     }
 
+    /// Returns an error naming the first of the given networks which does not exist.
+    async fn check_networks_exist(&self, network_names: &[String]) -> Result<()> {
+        let networks = self.docker.list_networks::<String>(None).await?;
+        for network_name in network_names {
+            let network_exists = networks
+                .iter()
+                .any(|n| n.name.as_ref() == Some(network_name));
+            if !network_exists {
+                bail!("Network {} does not exist", network_name);
+            }
+        }
+        Ok(())
+    }
+
+    async fn connect_network(&self, container_name: &str, network_name: &str) -> Result<()> {
+        info!(
+            "Connecting container {} to network {}",
+            container_name, network_name
+        );
+        let config = ConnectNetworkOptions {
+            container: container_name,
+            endpoint_config: EndpointSettings::default(),
+        };
+        self.docker.connect_network(network_name, config).await?;
+        Ok(())
+    }
+
     async fn create_container(
         &self,
-        id: &str,
         container_name: &str,
-        image: &str,
-        command: Option<&Vec<String>>,
-        env: Option<EnvVars>,
+        docker_job: &DockerJob,
     ) -> Result<ContainerCreateResponse> {
-        let cmd = command.map(|vec| vec.iter().map(|s| s.as_str()).collect());
+        let DockerJob {
+            id,
+            image,
+            command,
+            entrypoint,
+            working_dir,
+            env,
+            trace_id,
+            stop_signal,
+            stop_timeout,
+            extra_hosts,
+            restart_policy,
+            read_only_rootfs,
+            cap_add,
+            cap_drop,
+            no_new_privileges,
+            tmpfs,
+            user,
+            dns,
+            dns_search,
+            ulimits,
+            gpus,
+            shm_size,
+            hostname,
+            cpuset,
+            ..
+        } = docker_job;
+
+        let cmd = command
+            .as_ref()
+            .map(|vec| vec.iter().map(|s| s.as_str()).collect());
+        let entrypoint = entrypoint
+            .as_ref()
+            .map(|vec| vec.iter().map(|s| s.as_str()).collect());
 
         let options = Some(CreateContainerOptions {
             name: container_name,
             platform: None,
         });
 
-        // Merge the default agent environment variables with the job's environment variables
-        let mut resolved_env = env.unwrap_or_default();
-        if let Some(default_env) = SETTINGS.core.env.as_ref() {
-            resolved_env = resolved_env.merge_clone(default_env);
+        // Environment variables injected by foreman
+        let mut injected_env = EnvVars::new();
+        let scheme = if SETTINGS.core.tls_cert.is_some() {
+            "https"
+        } else {
+            "http"
+        };
+        let job_endpoint = job_endpoint(scheme, &SETTINGS.core.hostname, SETTINGS.core.port, id);
+        injected_env.inner_mut().extend([
+            ("FOREMAN_GET_JOB_ENDPOINT".to_string(), job_endpoint.clone()),
+            ("FOREMAN_PUT_JOB_ENDPOINT".to_string(), job_endpoint),
+        ]);
+        if let Some(trace_id) = trace_id {
+            injected_env
+                .inner_mut()
+                .insert("FOREMAN_TRACE_ID".to_string(), trace_id.clone());
         }
 
         // Convert env from HashMap to Vec<&str>
-        let mut env_strings: Vec<String> = resolved_env.into();
-        env_strings.push(format!(
-            "FOREMAN_GET_JOB_ENDPOINT=http://{}:{}/job/{}",
-            SETTINGS.core.hostname, SETTINGS.core.port, id
-        ));
-        env_strings.push(format!(
-            "FOREMAN_PUT_JOB_ENDPOINT=http://{}:{}/job/{}",
-            SETTINGS.core.hostname, SETTINGS.core.port, id
-        ));
+        let resolved_env = resolve_env(SETTINGS.core.env.as_ref(), env.as_ref(), injected_env);
+        resolved_env.validate()?;
+        let env_strings: Vec<String> = resolved_env.into();
         let env_strings: Vec<&str> = env_strings.iter().map(|s| s.as_str()).collect();
 
         // Container labels
         let mut labels = HashMap::new();
-        labels.insert("managed-by", "foreman");
+        if SETTINGS.core.apply_managed_label {
+            labels.insert("managed-by", "foreman");
+        }
 
         // Extra hosts
-        let extra_hosts = SETTINGS.core.extra_hosts.clone();
+        let extra_hosts =
+            resolve_extra_hosts(SETTINGS.core.extra_hosts.as_ref(), extra_hosts.as_ref())?;
+
+        // DNS servers and search domains. Job values take precedence over the core defaults.
+        let dns = dns.clone().or_else(|| SETTINGS.core.dns.clone());
+        if let Some(dns) = &dns {
+            validate_dns(dns)?;
+        }
+        let dns_search = dns_search
+            .clone()
+            .or_else(|| SETTINGS.core.dns_search.clone());
+
+        // Size of /dev/shm. Job values take precedence over the core default.
+        let shm_size = resolve_shm_size(shm_size.as_deref().or(SETTINGS.core.shm_size.as_deref()))?;
+
+        // Log driver
+        let log_config = SETTINGS
+            .core
+            .log_driver
+            .as_ref()
+            .map(|log_driver| HostConfigLogConfig {
+                typ: Some(log_driver.clone()),
+                config: SETTINGS.core.log_driver_opts.clone(),
+            });
+
+        // Ulimits
+        let ulimits = resolve_ulimits(SETTINGS.core.ulimits.as_ref(), ulimits.as_ref())?;
+
+        // Security options. Job values take precedence over the core defaults.
+        let read_only_rootfs = read_only_rootfs.unwrap_or(SETTINGS.core.read_only_rootfs);
+        let cap_add = cap_add.clone().or_else(|| SETTINGS.core.cap_add.clone());
+        let cap_drop = cap_drop.clone().or_else(|| SETTINGS.core.cap_drop.clone());
+        let security_opt = no_new_privileges
+            .unwrap_or(SETTINGS.core.no_new_privileges)
+            .then(|| vec!["no-new-privileges:true".to_string()]);
+
+        // tmpfs mounts
+        let tmpfs = tmpfs.as_ref().map(|tmpfs| {
+            tmpfs
+                .inner()
+                .iter()
+                .map(|(target, size)| (target.clone(), format!("size={}", size)))
+                .collect::<HashMap<String, String>>()
+        });
+
+        // User to run the container as. Falls back to the image's user when neither is set.
+        let user = user.as_deref().or(SETTINGS.core.default_user.as_deref());
+
+        let hostname = resolve_hostname(hostname.as_deref(), container_name)?;
+
+        // CPUs the container may run on. Not pinned when unset.
+        if let Some(cpuset) = cpuset {
+            validate_cpuset(cpuset)?;
+        }
 
         let config = Config {
-            image: Some(image),
+            image: Some(image.as_str()),
             cmd,
+            entrypoint,
+            working_dir: working_dir.as_deref(),
+            user,
+            hostname,
             host_config: Some(bollard::service::HostConfig {
                 network_mode: Some(SETTINGS.core.network_name.clone()),
                 extra_hosts,
+                dns,
+                dns_search,
+                ulimits,
+                shm_size,
+                cpuset_cpus: cpuset.clone(),
+                log_config,
+                device_requests: gpus.as_ref().map(|gpus| vec![gpus.into()]),
+                restart_policy: restart_policy.as_ref().map(Into::into),
+                readonly_rootfs: read_only_rootfs.then_some(true),
+                cap_add,
+                cap_drop,
+                security_opt,
+                tmpfs,
+                auto_remove: SETTINGS.core.auto_remove.then_some(true),
                 ..Default::default()
             }),
             env: Some(env_strings),
             labels: Some(labels),
+            stop_signal: stop_signal.as_deref(),
+            stop_timeout: stop_timeout.map(millis_to_secs),
             ..Default::default()
         };
 
-        info!("Created Docker container with name: {}", container_name);
+        info!(job_id = id.as_str(); "Created Docker container with name: {}", container_name);
         let container_create_response = self.docker.create_container(options, config).await?;
         Ok(container_create_response)
     }
 
-    async fn stop_container(&self, container_name: &str) -> Result<()> {
-        info!("Stopping container {}", container_name);
-        self.docker
-            .stop_container(container_name, Some(StopContainerOptions { t: 0 }))
-            .await?;
+    async fn stop_container(&self, container_name: &str, timeout: Option<u64>) -> Result<()> {
+        // Use the given timeout if any, then the stop timeout set on the container at create time,
+        // otherwise the default
+        let t = match self.inspect_container(container_name).await {
+            Ok(inspect_container_response) => timeout
+                .map(millis_to_secs)
+                .or_else(|| {
+                    inspect_container_response
+                        .config
+                        .and_then(|config| config.stop_timeout)
+                })
+                .unwrap_or_else(|| millis_to_secs(SETTINGS.core.stop_timeout)),
+            // Nothing to stop if the container never existed or is already gone
+            Err(e) if e.downcast_ref().is_some_and(is_not_found) => {
+                info!("Container {} not found, nothing to stop", container_name);
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        info!("Stopping container {} (timeout {}s)", container_name, t);
+        // Docker sends the container's stop signal, waiting up to `t` seconds for it to exit
+        match self
+            .docker
+            .stop_container(container_name, Some(StopContainerOptions { t }))
+            .await
+        {
+            Err(e) if is_not_modified(&e) => {
+                info!("Container {} already stopped", container_name);
+                return Ok(());
+            }
+            Err(e) if is_not_found(&e) => {
+                info!("Container {} not found, nothing to stop", container_name);
+                return Ok(());
+            }
+            // The container may still be running e.g. if the request timed out, so fall through
+            // to checking
+            Err(e) => warn!("Error stopping container {}: {}", container_name, e),
+            Ok(_) => {}
+        }
+
+        let state = self.inspect_container(container_name).await?.state;
+        let running = state.as_ref().and_then(|state| state.running);
+        if running == Some(true) {
+            warn!(
+                "Container {} still running after stop, escalating to SIGKILL",
+                container_name
+            );
+            let options = Some(KillContainerOptions { signal: "SIGKILL" });
+            match self.docker.kill_container(container_name, options).await {
+                Err(e) if is_not_found(&e) || has_status_code(&e, 409) => {}
+                result => result?,
+            }
+        } else if state.and_then(|state| state.exit_code) == Some(SIGKILL_EXIT_CODE) {
+            info!(
+                "Container {} did not exit within {}s of it's stop signal, killed",
+                container_name, t
+            );
+        } else {
+            info!("Container {} exited after it's stop signal", container_name);
+        }
         Ok(())
     }
 
-    async fn remove_container(&self, container_name: &str) -> Result<()> {
-        info!("Removing container {}", container_name);
-        self.docker.remove_container(container_name, None).await?;
+    async fn remove_container(&self, container_name: &str, force: bool) -> Result<()> {
+        info!("Removing container {} (force {})", container_name, force);
+        let options = Some(RemoveContainerOptions {
+            force,
+            ..Default::default()
+        });
+        match self.docker.remove_container(container_name, options).await {
+            // The container may already be gone e.g., if it was auto-removed by Docker or was
+            // never created
+            Err(e) if is_not_found(&e) => {
+                info!("Container {} already removed", container_name);
+            }
+            result => result?,
+        }
         Ok(())
     }
 
     async fn start_container(&self, container_name: &str) -> Result<()> {
         info!("Starting container: {}", container_name);
-        self.docker
-            .start_container(container_name, None::<StartContainerOptions<String>>)
-            .await?;
+        // Retry transient failures so a momentary Docker hiccup doesn't fail the job
+        retry(
+            SETTINGS.docker.start_attempts.max(1),
+            Duration::from_millis(SETTINGS.docker.start_retry_backoff),
+            || {
+                self.docker
+                    .start_container(container_name, None::<StartContainerOptions<String>>)
+            },
+        )
+        .await?;
+        tracing::info!(container_name, "container started");
         Ok(())
     }
 
@@ -173,32 +919,70 @@ impl DockerExecutor {
         Ok(exists)
     }
 
-    async fn run(&mut self, docker_job: &DockerJob) -> Result<()> {
+    /// Check the job's image is resolvable, either locally or by contacting the registry,
+    /// without pulling the image or creating a container.
+    async fn check(&self, docker_job: &DockerJob) -> Result<()> {
         let DockerJob {
             id,
             image,
             always_pull,
-            env,
-            command,
             ..
         } = docker_job;
 
-        let container_name = format!("job-{}", id);
+        if !*always_pull && self.image_exists(image).await? {
+            info!(job_id = id.as_str(); "Image {} exists", image);
+            return Ok(());
+        }
+        if let Err(e) = self.docker.inspect_registry_image(image, None).await {
+            bail!("Image {} could not be resolved: {}", image, e);
+        }
+        info!(job_id = id.as_str(); "Image {} resolved from registry", image);
+        Ok(())
+    }
+
+    /// Verify the job's additional networks exist and pull it's image if required.
+    async fn prepare_job(&self, docker_job: &DockerJob) -> Result<()> {
+        let DockerJob {
+            id,
+            image,
+            always_pull,
+            networks,
+            callback_url,
+            ..
+        } = docker_job;
+
+        // Fail the job up front rather than each of it's updates
+        validate_callback_url(callback_url)?;
+        // Verify any additional networks exist before creating anything
+        if let Some(networks) = networks {
+            self.check_networks_exist(networks).await?;
+        }
         // Pull image?
         if *always_pull {
-            self.pull(image).await?;
+            self.pull(id, image).await?;
         } else {
             let image_exists = self.image_exists(image).await?;
             if !image_exists {
-                info!("Image {} does not exist, pulling...", image);
-                self.pull(image).await?;
+                info!(job_id = id.as_str(); "Image {} does not exist, pulling...", image);
+                self.pull(id, image).await?;
             } else {
-                info!("Image {} exists, skipping pull...", image)
+                info!(job_id = id.as_str(); "Image {} exists, skipping pull...", image)
             }
         }
+        Ok(())
+    }
+
+    /// Create and start the job's container. The job must have been prepared.
+    async fn start_job(&mut self, docker_job: &DockerJob) -> Result<()> {
+        let DockerJob { id, networks, .. } = docker_job;
+
+        let container_name = format!("job-{}", id);
         // Create container
-        self.create_container(id, &container_name, image, command.as_ref(), env.clone())
-            .await?;
+        self.create_container(&container_name, docker_job).await?;
+        // Connect additional networks. The primary network remains `core.network_name`.
+        for network_name in networks.iter().flatten() {
+            self.connect_network(&container_name, network_name).await?;
+        }
         // Start container
         self.start_container(&container_name).await?;
         Ok(())
@@ -209,24 +993,382 @@ impl JobExecutor for DockerExecutor {
     // Allowing irrefutable_let_patterns as currently there is only one Job variant.
     // Remove if/when other variants are added.
     #[allow(irrefutable_let_patterns)]
-    async fn execute(&mut self, job: Job) -> Result<()> {
+    async fn prepare(&self, job: &Job) -> Result<()> {
         if let Job::Docker(docker_job) = job {
-            self.run(&docker_job).await?;
+            self.prepare_job(docker_job).await?;
+        } else {
+            bail!("Expected docker job");
+        }
+        Ok(())
+    }
+
+    #[allow(irrefutable_let_patterns)]
+    async fn start(&mut self, job: Job) -> Result<()> {
+        if let Job::Docker(docker_job) = job {
+            self.start_job(&docker_job).await?;
+        } else {
+            bail!("Expected docker job");
+        }
+        Ok(())
+    }
+
+    #[allow(irrefutable_let_patterns)]
+    async fn validate(&mut self, job: Job) -> Result<()> {
+        if let Job::Docker(docker_job) = job {
+            validate_job_id(&docker_job.id)?;
+            validate_callback_url(&docker_job.callback_url)?;
+            if let Some(env) = &docker_job.env {
+                env.validate()?;
+            }
+            if let Some(dns) = &docker_job.dns {
+                validate_dns(dns)?;
+            }
+            if let Some(hostname) = &docker_job.hostname {
+                validate_hostname(hostname)?;
+            }
+            if let Some(cpuset) = &docker_job.cpuset {
+                validate_cpuset(cpuset)?;
+            }
+            if let Some(ulimits) = &docker_job.ulimits {
+                validate_ulimits(ulimits)?;
+            }
+            resolve_shm_size(docker_job.shm_size.as_deref())?;
+            self.check(&docker_job).await?;
         } else {
             bail!("Expected docker job");
         }
         Ok(())
     }
 
-    async fn stop(&mut self, job_id: &str) -> Result<()> {
+    async fn stop(&mut self, job_id: &str, timeout: Option<u64>) -> Result<()> {
         let container_name = format!("job-{}", job_id);
-        self.stop_container(&container_name).await?;
+        self.stop_container(&container_name, timeout).await?;
         Ok(())
     }
 
-    async fn remove(&mut self, job_id: &str) -> Result<()> {
+    async fn remove(&mut self, job_id: &str, force: bool) -> Result<()> {
         let container_name = format!("job-{}", job_id);
-        self.remove_container(&container_name).await?;
+        self.remove_container(&container_name, force).await?;
         Ok(())
     }
+
+    async fn exit(&self, job_id: &str) -> Result<Option<ContainerExit>> {
+        // Running jobs are inspected in one batch by the `Exits` command, which reports the exit
+        // code of each job whose container has exited back to the reconcile task
+        let container_name = format!("job-{}", job_id);
+        let state = match self.docker.inspect_container(&container_name, None).await {
+            Ok(inspect_container_response) => inspect_container_response.state,
+            Err(e) if is_not_found(&e) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let Some(state) = state else {
+            return Ok(None);
+        };
+        // Only exited or dead containers have an exit code, created ones haven't started yet
+        let exited = matches!(
+            state.status,
+            Some(ContainerStateStatusEnum::EXITED | ContainerStateStatusEnum::DEAD)
+        );
+        if !exited {
+            return Ok(None);
+        }
+        Ok(Some(ContainerExit {
+            exit_code: state.exit_code,
+            oom_killed: state.oom_killed.unwrap_or(false),
+        }))
+    }
+
+    async fn stats(&mut self, job_id: &str) -> Result<Option<JobStats>> {
+        let container_name = format!("job-{}", job_id);
+        // One-shot samples don't include the previous CPU usage, so CPU usage is calculated from
+        // two samples taken a short interval apart rather than waiting for Docker's next sample
+        let Some(previous) = self.stats_sample(&container_name).await? else {
+            return Ok(None);
+        };
+        tokio::time::sleep(STATS_SAMPLE_INTERVAL).await;
+        let Some(mut stats) = self.stats_sample(&container_name).await? else {
+            return Ok(None);
+        };
+        stats.precpu_stats = previous.cpu_stats;
+        Ok(Some(JobStats::from(&stats)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_vars(pairs: &[(&str, &str)]) -> EnvVars {
+        let mut env_vars = EnvVars::new();
+        env_vars
+            .inner_mut()
+            .extend(pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+        env_vars
+    }
+
+    #[test]
+    fn test_cpu_percent() {
+        // Used 1/4 of the system's CPU time across 4 CPUs i.e., one CPU fully utilised
+        assert_eq!(cpu_percent(200, 100, 1400, 1000, 4), 100.0);
+        assert_eq!(cpu_percent(100, 100, 1400, 1000, 4), 0.0);
+        // First sample e.g., container just started
+        assert_eq!(cpu_percent(100, 0, 0, 0, 4), 0.0);
+    }
+
+    #[test]
+    fn test_error_status_codes() {
+        let error = |status_code| bollard::errors::Error::DockerResponseServerError {
+            status_code,
+            message: String::new(),
+        };
+        assert!(is_not_found(&error(404)));
+        assert!(!is_not_found(&error(500)));
+        assert!(is_not_modified(&error(304)));
+        assert!(!is_not_modified(&error(404)));
+    }
+
+    #[test]
+    fn test_job_endpoint() {
+        assert_eq!(
+            job_endpoint("http", "host.docker.internal", 3000, "abc"),
+            "http://host.docker.internal:3000/job/abc"
+        );
+        assert_eq!(
+            job_endpoint("http", "10.0.0.1", 3000, "abc"),
+            "http://10.0.0.1:3000/job/abc"
+        );
+        assert_eq!(
+            job_endpoint("https", "fd00::1", 3000, "abc"),
+            "https://[fd00::1]:3000/job/abc"
+        );
+        assert!(reqwest::Url::parse(&job_endpoint("http", "::1", 3000, "abc")).is_ok());
+    }
+
+    #[test]
+    fn test_resolve_env() {
+        let default_env = env_vars(&[("A", "default"), ("B", "default")]);
+        let job_env = env_vars(&[
+            ("B", "job"),
+            ("C", "job"),
+            ("FOREMAN_GET_JOB_ENDPOINT", "http://evil"),
+        ]);
+        let injected_env = env_vars(&[("FOREMAN_GET_JOB_ENDPOINT", "http://foreman:3000/job/1")]);
+
+        let resolved = resolve_env(Some(&default_env), Some(&job_env), injected_env);
+
+        assert_eq!(
+            resolved,
+            env_vars(&[
+                ("A", "default"),
+                ("B", "job"),
+                ("C", "job"),
+                ("FOREMAN_GET_JOB_ENDPOINT", "http://foreman:3000/job/1"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_connect() {
+        assert!(connect(Some("tcp://localhost:2375"), None, None).is_ok());
+        assert!(connect(Some("http://localhost:2375"), None, None).is_ok());
+        assert!(connect(Some("https://localhost:2376"), None, None).is_err());
+        assert!(connect(Some("ftp://localhost:2375"), None, None).is_err());
+        assert!(connect(Some("localhost:2375"), None, None).is_err());
+
+        let api_version = parse_api_version("1.41").unwrap();
+        let docker = connect(Some("tcp://localhost:2375"), None, Some(&api_version)).unwrap();
+        assert_eq!(docker.client_version(), api_version);
+    }
+
+    #[test]
+    fn test_parse_api_version() {
+        assert_eq!(
+            parse_api_version("1.41").unwrap(),
+            ClientVersion {
+                major_version: 1,
+                minor_version: 41
+            }
+        );
+        assert!(parse_api_version("1").is_err());
+        assert!(parse_api_version("1.x").is_err());
+        assert!(parse_api_version("v1.41").is_err());
+    }
+
+    #[test]
+    fn test_resolve_extra_hosts() {
+        let global = vec![
+            "host.docker.internal:host-gateway".to_string(),
+            "db:10.0.0.1".to_string(),
+        ];
+        let job = vec!["db:10.0.0.2".to_string(), "cache:::1".to_string()];
+
+        let resolved = resolve_extra_hosts(Some(&global), Some(&job)).unwrap();
+
+        assert_eq!(
+            resolved,
+            Some(vec![
+                "host.docker.internal:host-gateway".to_string(),
+                "db:10.0.0.2".to_string(),
+                "cache:::1".to_string(),
+            ])
+        );
+        assert_eq!(resolve_extra_hosts(None, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_resolve_extra_hosts_rejects_malformed_entries() {
+        for entry in ["db", ":10.0.0.1", "db:not-an-ip"] {
+            let job = vec![entry.to_string()];
+            assert!(
+                resolve_extra_hosts(None, Some(&job)).is_err(),
+                "expected '{}' to be rejected",
+                entry
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_dns() {
+        let valid = vec!["10.0.0.53".to_string(), "2001:4860:4860::8888".to_string()];
+        assert!(validate_dns(&valid).is_ok());
+        let invalid = vec!["10.0.0.53".to_string(), "dns.example.com".to_string()];
+        assert!(validate_dns(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_validate_cpuset() {
+        for cpuset in ["0", "0-3", "0,2", "0-1,4,6-7"] {
+            assert!(validate_cpuset(cpuset).is_ok(), "{}", cpuset);
+        }
+        for cpuset in ["", "3-1", "0,", "a", "0-", "-1", "0 ,2", "0-2-4"] {
+            assert!(validate_cpuset(cpuset).is_err(), "{}", cpuset);
+        }
+    }
+
+    #[test]
+    fn test_resolve_hostname() {
+        assert_eq!(
+            resolve_hostname(Some("worker-1.jobs.internal"), "job-123abc").unwrap(),
+            Some("worker-1.jobs.internal")
+        );
+        assert_eq!(
+            resolve_hostname(None, "job-123abc").unwrap(),
+            Some("job-123abc")
+        );
+        // Container names which aren't valid hostnames are left to Docker
+        assert_eq!(resolve_hostname(None, "job-123_abc").unwrap(), None);
+        let long_name = format!("job-{}", "a".repeat(64));
+        assert_eq!(resolve_hostname(None, &long_name).unwrap(), None);
+
+        assert!(resolve_hostname(Some("-worker"), "job-123abc").is_err());
+        assert!(resolve_hostname(Some("worker..internal"), "job-123abc").is_err());
+        assert!(resolve_hostname(Some("worker_1"), "job-123abc").is_err());
+    }
+
+    #[test]
+    fn test_resolve_ulimits() {
+        let ulimit = |soft, hard| crate::job::Ulimit { soft, hard };
+        let global = Ulimits::from([
+            ("nofile".to_string(), ulimit(1024, 4096)),
+            ("nproc".to_string(), ulimit(512, 512)),
+        ]);
+        let job = Ulimits::from([("nofile".to_string(), ulimit(8192, 16384))]);
+
+        let resolved = resolve_ulimits(Some(&global), Some(&job)).unwrap().unwrap();
+
+        assert_eq!(
+            resolved,
+            vec![
+                ResourcesUlimits {
+                    name: Some("nofile".to_string()),
+                    soft: Some(8192),
+                    hard: Some(16384),
+                },
+                ResourcesUlimits {
+                    name: Some("nproc".to_string()),
+                    soft: Some(512),
+                    hard: Some(512),
+                },
+            ]
+        );
+        assert_eq!(resolve_ulimits(None, None).unwrap(), None);
+
+        let unknown = Ulimits::from([("files".to_string(), ulimit(1, 1))]);
+        assert!(resolve_ulimits(None, Some(&unknown)).is_err());
+        let soft_exceeds_hard = Ulimits::from([("nofile".to_string(), ulimit(2, 1))]);
+        assert!(resolve_ulimits(None, Some(&soft_exceeds_hard)).is_err());
+    }
+
+    #[test]
+    fn test_device_request_from_gpus() {
+        let all: DeviceRequest = (&Gpus::All).into();
+        assert_eq!(all.driver.as_deref(), Some("nvidia"));
+        assert_eq!(all.count, Some(-1));
+        assert_eq!(all.capabilities, Some(vec![vec!["gpu".to_string()]]));
+
+        let count: DeviceRequest = (&Gpus::Count(2)).into();
+        assert_eq!(count.count, Some(2));
+        assert_eq!(count.device_ids, None);
+
+        let ids: DeviceRequest = (&Gpus::DeviceIds(vec!["0".to_string()])).into();
+        assert_eq!(ids.count, None);
+        assert_eq!(ids.device_ids, Some(vec!["0".to_string()]));
+    }
+
+    #[test]
+    fn test_resolve_shm_size() {
+        assert_eq!(resolve_shm_size(None).unwrap(), None);
+        assert_eq!(
+            resolve_shm_size(Some("512m")).unwrap(),
+            Some(512 * 1024 * 1024)
+        );
+        assert_eq!(
+            resolve_shm_size(Some("1g")).unwrap(),
+            Some(1024 * 1024 * 1024)
+        );
+        assert!(resolve_shm_size(Some("0")).is_err());
+        assert!(resolve_shm_size(Some("lots")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let server_error = || bollard::errors::Error::DockerResponseServerError {
+            status_code: 500,
+            message: "server error".to_string(),
+        };
+        let mut calls = 0;
+        let result = retry(3, Duration::from_millis(1), || {
+            calls += 1;
+            let result = if calls < 3 {
+                Err(server_error())
+            } else {
+                Ok(calls)
+            };
+            future::ready(result)
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+
+        let mut calls = 0;
+        let result: Result<()> = retry(2, Duration::from_millis(1), || {
+            calls += 1;
+            future::ready(Err(server_error()))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 2);
+
+        // Client errors aren't retried
+        let mut calls = 0;
+        let result: Result<()> = retry(3, Duration::from_millis(1), || {
+            calls += 1;
+            future::ready(Err(bollard::errors::Error::DockerResponseServerError {
+                status_code: 404,
+                message: "no such container".to_string(),
+            }))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
 }
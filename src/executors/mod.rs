@@ -2,18 +2,90 @@ mod docker;
 
 pub use docker::*;
 
+use std::time::Instant;
+
 use anyhow::Result;
+use serde::Serialize;
+use tokio::sync::oneshot;
+use tracing::Span;
 
 use crate::job::Job;
 
+/// Summary of a job's current resource usage.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct JobStats {
+    /// CPU usage as a percentage of a single CPU e.g., 200.0 is two CPUs fully utilised.
+    pub cpu_percent: f64,
+    pub memory_usage_bytes: u64,
+    pub memory_limit_bytes: Option<u64>,
+    pub pids: Option<u64>,
+}
+
+/// How a job's container exited.
+#[derive(Debug, PartialEq)]
+pub struct ContainerExit {
+    pub exit_code: Option<i64>,
+    /// Whether the container was killed after exceeding it's memory limit.
+    pub oom_killed: bool,
+}
+
 pub trait JobExecutor {
-    async fn execute(&mut self, job: Job) -> Result<()>;
-    async fn stop(&mut self, job_id: &str) -> Result<()>;
-    async fn remove(&mut self, job_id: &str) -> Result<()>;
+    /// Prepare to run a job e.g., pull it's image. Jobs may be prepared concurrently.
+    async fn prepare(&self, job: &Job) -> Result<()>;
+    /// Start a prepared job.
+    async fn start(&mut self, job: Job) -> Result<()>;
+    async fn validate(&mut self, job: Job) -> Result<()>;
+    /// Stop a job, waiting up to `timeout` milliseconds for it to exit before killing it. Uses
+    /// the job's own stop timeout when `timeout` is `None`.
+    async fn stop(&mut self, job_id: &str, timeout: Option<u64>) -> Result<()>;
+    async fn remove(&mut self, job_id: &str, force: bool) -> Result<()>;
+    /// Returns `None` if the job's container no longer exists.
+    async fn stats(&mut self, job_id: &str) -> Result<Option<JobStats>>;
+    /// Returns `None` if the job's container is still running or no longer exists.
+    async fn exit(&self, job_id: &str) -> Result<Option<ContainerExit>>;
 }
 
 pub enum JobExecutorCommand {
-    Execute { job: Job },
-    Stop { job_id: String },
-    Remove { job_id: String },
+    Execute {
+        job: Box<Job>,
+        span: Span,
+    },
+    /// Sent by the manager task once a job has been prepared, to start it.
+    Start {
+        job: Box<Job>,
+        span: Span,
+    },
+    Validate {
+        job: Box<Job>,
+        resp: oneshot::Sender<Result<()>>,
+    },
+    Stop {
+        job_id: String,
+    },
+    /// Sent on shutdown to stop a job within `core.shutdown_grace_period`, killing it if it's
+    /// still running at `deadline`. Processed concurrently so jobs exit in parallel.
+    Terminate {
+        job_id: String,
+        deadline: Instant,
+        resp: oneshot::Sender<()>,
+    },
+    Remove {
+        job_id: String,
+        /// Remove the container even if it's still running.
+        force: bool,
+    },
+    Stats {
+        job_id: String,
+        resp: oneshot::Sender<Result<Option<JobStats>>>,
+    },
+    /// Check which of the jobs' containers have exited, and how. Jobs whose containers are still
+    /// running, no longer exist or couldn't be inspected are omitted.
+    Exits {
+        job_ids: Vec<String>,
+        resp: oneshot::Sender<Vec<(String, ContainerExit)>>,
+    },
+    /// Replies once all previously sent commands have been processed.
+    Flush {
+        resp: oneshot::Sender<()>,
+    },
 }
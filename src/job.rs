@@ -1,19 +1,272 @@
-use serde::Deserialize;
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use anyhow::bail;
+use serde::{
+    de::{self, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
 use serde_json::Value;
+use urlencoding::encode;
 
 use crate::env::EnvVars;
 
+/// Docker restart policy for a job's container.
+/// Parsed from one of `no`, `on-failure`, `on-failure:N`, `always` or `unless-stopped`.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+#[serde(try_from = "String")]
+pub enum RestartPolicy {
+    No,
+    OnFailure(Option<u32>),
+    Always,
+    UnlessStopped,
+}
+
+impl FromStr for RestartPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let policy = match s.split_once(':') {
+            None => match s {
+                "no" => RestartPolicy::No,
+                "on-failure" => RestartPolicy::OnFailure(None),
+                "always" => RestartPolicy::Always,
+                "unless-stopped" => RestartPolicy::UnlessStopped,
+                _ => bail!("Unknown restart policy '{}'", s),
+            },
+            Some(("on-failure", max_retries)) => match max_retries.parse() {
+                Ok(max_retries) => RestartPolicy::OnFailure(Some(max_retries)),
+                Err(_) => bail!("Invalid maximum retry count in restart policy '{}'", s),
+            },
+            Some(_) => bail!("Unknown restart policy '{}'", s),
+        };
+        Ok(policy)
+    }
+}
+
+impl TryFrom<String> for RestartPolicy {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> anyhow::Result<Self> {
+        s.parse()
+    }
+}
+
+/// Parse a human-friendly size such as `512`, `64k`, `512m` or `1g` into a number of bytes.
+pub fn parse_size(s: &str) -> anyhow::Result<u64> {
+    let s = s.trim().to_lowercase();
+    let s = s.strip_suffix('b').unwrap_or(&s);
+    let (digits, multiplier) = match s.chars().last() {
+        Some('k') => (&s[..s.len() - 1], 1024),
+        Some('m') => (&s[..s.len() - 1], 1024 * 1024),
+        Some('g') => (&s[..s.len() - 1], 1024 * 1024 * 1024),
+        _ => (s, 1),
+    };
+    match digits.parse::<u64>() {
+        Ok(n) => match n.checked_mul(multiplier) {
+            Some(bytes) => Ok(bytes),
+            None => bail!("Size '{}' is too large", s),
+        },
+        Err(_) => bail!(
+            "Invalid size '{}', expected a number with an optional k, m or g suffix",
+            s
+        ),
+    }
+}
+
+/// tmpfs mounts for a job's container, as a mapping of target path to size
+/// e.g. `{ "/tmp": "64m" }`. Deserialization rejects duplicate targets, relative targets and
+/// invalid sizes.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct TmpfsMounts(Vec<(String, String)>);
+
+impl TmpfsMounts {
+    pub fn inner(&self) -> &Vec<(String, String)> {
+        &self.0
+    }
+}
+
+impl<'de> Deserialize<'de> for TmpfsMounts {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TmpfsMountsVisitor;
+
+        impl<'de> Visitor<'de> for TmpfsMountsVisitor {
+            type Value = TmpfsMounts;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of tmpfs target paths to sizes")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut mounts: Vec<(String, String)> = Vec::new();
+                while let Some((target, size)) = map.next_entry::<String, String>()? {
+                    if !target.starts_with('/') {
+                        return Err(de::Error::custom(format!(
+                            "tmpfs target '{}' must be an absolute path",
+                            target
+                        )));
+                    }
+                    if mounts.iter().any(|(t, _)| *t == target) {
+                        return Err(de::Error::custom(format!(
+                            "duplicate tmpfs target '{}'",
+                            target
+                        )));
+                    }
+                    parse_size(&size).map_err(de::Error::custom)?;
+                    mounts.push((target, size));
+                }
+                Ok(TmpfsMounts(mounts))
+            }
+        }
+
+        deserializer.deserialize_map(TmpfsMountsVisitor)
+    }
+}
+
+/// GPUs to request for a job's container. Deserialized from `"all"`, a number of GPUs, or a list of
+/// GPU device ids e.g. `["0", "GPU-3a23c669"]`.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+#[serde(try_from = "GpusValue")]
+pub enum Gpus {
+    All,
+    Count(u32),
+    DeviceIds(Vec<String>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GpusValue {
+    Count(u32),
+    Name(String),
+    DeviceIds(Vec<String>),
+}
+
+impl TryFrom<GpusValue> for Gpus {
+    type Error = anyhow::Error;
+
+    fn try_from(value: GpusValue) -> anyhow::Result<Self> {
+        let gpus = match value {
+            GpusValue::Name(name) if name == "all" => Gpus::All,
+            GpusValue::Name(name) => bail!("Invalid gpus '{}', expected 'all'", name),
+            GpusValue::Count(0) => bail!("Invalid gpus, count must be greater than 0"),
+            GpusValue::Count(count) => Gpus::Count(count),
+            GpusValue::DeviceIds(ids) if ids.is_empty() => {
+                bail!("Invalid gpus, device ids must not be empty")
+            }
+            GpusValue::DeviceIds(ids) => Gpus::DeviceIds(ids),
+        };
+        Ok(gpus)
+    }
+}
+
+/// Names of the ulimits supported by Docker.
+const ULIMIT_NAMES: &[&str] = &[
+    "core",
+    "cpu",
+    "data",
+    "fsize",
+    "locks",
+    "memlock",
+    "msgqueue",
+    "nice",
+    "nofile",
+    "nproc",
+    "rss",
+    "rtprio",
+    "rttime",
+    "sigpending",
+    "stack",
+];
+
+/// Soft and hard limits of a single ulimit e.g. `{ "soft": 1024, "hard": 4096 }`.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy)]
+pub struct Ulimit {
+    pub soft: i64,
+    pub hard: i64,
+}
+
+/// HTTP method used to forward a job's updates to it's callback URL.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CallbackMethod {
+    #[default]
+    Put,
+    Post,
+    Patch,
+}
+
+impl From<CallbackMethod> for reqwest::Method {
+    fn from(method: CallbackMethod) -> Self {
+        match method {
+            CallbackMethod::Put => reqwest::Method::PUT,
+            CallbackMethod::Post => reqwest::Method::POST,
+            CallbackMethod::Patch => reqwest::Method::PATCH,
+        }
+    }
+}
+
+/// Ulimits for a job's container, as a mapping of ulimit name to limits
+/// e.g. `{ "nofile": { "soft": 1024, "hard": 4096 } }`.
+pub type Ulimits = HashMap<String, Ulimit>;
+
+/// Check each ulimit is known to Docker and it's soft limit doesn't exceed it's hard limit.
+pub fn validate_ulimits(ulimits: &Ulimits) -> anyhow::Result<()> {
+    for (name, Ulimit { soft, hard }) in ulimits {
+        if !ULIMIT_NAMES.contains(&name.as_str()) {
+            bail!(
+                "Unknown ulimit '{}', expected one of {}",
+                name,
+                ULIMIT_NAMES.join(", ")
+            );
+        }
+        if soft > hard {
+            bail!(
+                "Invalid ulimit '{}', soft limit {} exceeds hard limit {}",
+                name,
+                soft,
+                hard
+            );
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct DockerJob {
     pub id: String,
     pub image: String,
     pub command: Option<Vec<String>>,
+    pub entrypoint: Option<Vec<String>>,
+    pub working_dir: Option<String>,
     pub body: Value,
     pub env: Option<EnvVars>,
     pub callback_url: String,
+    /// Overrides the `core.callback_method` setting for this job.
+    pub callback_method: Option<CallbackMethod>,
     #[serde(default)]
     pub always_pull: bool,
+    pub trace_id: Option<String>,
+    pub stop_signal: Option<String>,
+    pub stop_timeout: Option<u64>,
+    pub extra_hosts: Option<Vec<String>>,
+    pub networks: Option<Vec<String>>,
+    pub restart_policy: Option<RestartPolicy>,
+    pub read_only_rootfs: Option<bool>,
+    pub cap_add: Option<Vec<String>>,
+    pub cap_drop: Option<Vec<String>>,
+    pub no_new_privileges: Option<bool>,
+    pub tmpfs: Option<TmpfsMounts>,
+    pub user: Option<String>,
+    pub timeout: Option<u64>,
+    pub dns: Option<Vec<String>>,
+    pub dns_search: Option<Vec<String>>,
+    pub ulimits: Option<Ulimits>,
+    pub gpus: Option<Gpus>,
+    pub shm_size: Option<String>,
+    /// Hostname of the job's container. Defaults to the container's name.
+    pub hostname: Option<String>,
+    /// CPUs the job's container is pinned to e.g. `0-3` or `0,2`.
+    pub cpuset: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -24,6 +277,106 @@ pub enum Job {
     Docker(DockerJob),
 }
 
+impl Job {
+    pub fn id(&self) -> &str {
+        let Job::Docker(DockerJob { id, .. }) = self;
+        id
+    }
+}
+
+/// The content type of control server responses streaming one JSON job per line.
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Incrementally decodes newline delimited JSON jobs from chunks of a response body.
+#[derive(Debug, Default)]
+pub struct NdjsonJobs {
+    buf: Vec<u8>,
+}
+
+impl NdjsonJobs {
+    /// Append a chunk, returning the jobs from any lines it completes. Blank lines are ignored.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<anyhow::Result<Job>> {
+        self.buf.extend_from_slice(chunk);
+        let mut jobs = Vec::new();
+        while let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=pos).collect();
+            jobs.extend(parse_ndjson_line(&line));
+        }
+        jobs
+    }
+
+    /// Decode the final line, which may not be terminated by a newline.
+    pub fn finish(self) -> Option<anyhow::Result<Job>> {
+        parse_ndjson_line(&self.buf)
+    }
+}
+
+fn parse_ndjson_line(line: &[u8]) -> Option<anyhow::Result<Job>> {
+    if line.trim_ascii().is_empty() {
+        return None;
+    }
+    Some(serde_json::from_slice(line).map_err(anyhow::Error::from))
+}
+
+/// Placeholders which may be used in a job's `callbackUrl`, substituted as each update is sent.
+const CALLBACK_URL_PLACEHOLDERS: [&str; 2] = ["id", "status"];
+
+/// Substitute placeholders e.g. `{id}` in a callback URL with the URL encoded result of `value`
+/// for each placeholder's name. Returns an error for unknown or unterminated placeholders, rather
+/// than sending them literally.
+pub fn render_callback_url(
+    template: &str,
+    value: impl Fn(&str) -> String,
+) -> anyhow::Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            bail!("Unterminated placeholder in callback URL '{}'", template);
+        };
+        let name = &rest[start + 1..start + len];
+        if !CALLBACK_URL_PLACEHOLDERS.contains(&name) {
+            bail!(
+                "Unknown placeholder '{{{}}}' in callback URL '{}', expected one of {}",
+                name,
+                template,
+                CALLBACK_URL_PLACEHOLDERS
+                    .map(|name| format!("{{{}}}", name))
+                    .join(", ")
+            );
+        }
+        rendered.push_str(&encode(&value(name)));
+        rest = &rest[start + len + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Check a callback URL only contains known placeholders.
+pub fn validate_callback_url(template: &str) -> anyhow::Result<()> {
+    render_callback_url(template, |_| String::new())?;
+    Ok(())
+}
+
+/// Check a job id only contains ASCII alphanumeric characters, `-` and `_`.
+/// Job ids are used in container names and URL paths so must be restricted to a safe charset.
+pub fn validate_job_id(id: &str) -> anyhow::Result<()> {
+    if id.is_empty() {
+        bail!("Job id must not be empty");
+    }
+    if !id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!(
+            "Invalid job id '{}', must only contain alphanumeric characters, '-' and '_'",
+            id
+        );
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use serde_json::json;
@@ -58,6 +411,7 @@ mod tests {
                 env,
                 callback_url,
                 always_pull,
+                ..
             }) => {
                 let mut test_env = EnvVars::new();
                 test_env
@@ -73,9 +427,420 @@ mod tests {
                 assert_eq!(body, json!({ "foo": "bar", "eggs": "spam" }));
                 assert_eq!(env, Some(test_env));
                 assert_eq!(callback_url, "https://api.example.com/callback");
-                assert_eq!(always_pull, true);
+                assert!(always_pull);
             }
+            // Allowing unreachable_patterns as currently there is only one Job variant.
+            #[allow(unreachable_patterns)]
             _ => panic!("Invalid job variant"),
         }
     }
+
+    #[test]
+    fn test_deserialize_docker_job_with_trace_id() {
+        let json = r#"{
+            "id": "123abc",
+            "image": "alpine:latest",
+            "body": null,
+            "callbackUrl": "https://api.example.com/callback",
+            "traceId": "4bf92f3577b34da6a3ce929d0e0e4736"
+        }"#;
+
+        let Job::Docker(docker_job) = serde_json::from_str(json).unwrap();
+
+        assert_eq!(
+            docker_job.trace_id,
+            Some("4bf92f3577b34da6a3ce929d0e0e4736".to_string())
+        );
+    }
+
+    #[test]
+    fn test_deserialize_docker_job_with_stop_signal() {
+        let json = r#"{
+            "id": "123abc",
+            "image": "alpine:latest",
+            "body": null,
+            "callbackUrl": "https://api.example.com/callback",
+            "stopSignal": "SIGQUIT",
+            "stopTimeout": 5000
+        }"#;
+
+        let Job::Docker(docker_job) = serde_json::from_str(json).unwrap();
+
+        assert_eq!(docker_job.stop_signal, Some("SIGQUIT".to_string()));
+        assert_eq!(docker_job.stop_timeout, Some(5000));
+    }
+
+    #[test]
+    fn test_deserialize_docker_job_with_networks() {
+        let json = r#"{
+            "id": "123abc",
+            "image": "alpine:latest",
+            "body": null,
+            "callbackUrl": "https://api.example.com/callback",
+            "networks": ["backend", "monitoring"]
+        }"#;
+
+        let Job::Docker(docker_job) = serde_json::from_str(json).unwrap();
+
+        assert_eq!(
+            docker_job.networks,
+            Some(vec!["backend".to_string(), "monitoring".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_parse_restart_policy() {
+        assert_eq!("no".parse::<RestartPolicy>().unwrap(), RestartPolicy::No);
+        assert_eq!(
+            "on-failure".parse::<RestartPolicy>().unwrap(),
+            RestartPolicy::OnFailure(None)
+        );
+        assert_eq!(
+            "on-failure:3".parse::<RestartPolicy>().unwrap(),
+            RestartPolicy::OnFailure(Some(3))
+        );
+        assert_eq!(
+            "always".parse::<RestartPolicy>().unwrap(),
+            RestartPolicy::Always
+        );
+        assert_eq!(
+            "unless-stopped".parse::<RestartPolicy>().unwrap(),
+            RestartPolicy::UnlessStopped
+        );
+        assert!("sometimes".parse::<RestartPolicy>().is_err());
+        assert!("on-failure:lots".parse::<RestartPolicy>().is_err());
+        assert!("always:3".parse::<RestartPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_deserialize_docker_job_with_restart_policy() {
+        let json = r#"{
+            "id": "123abc",
+            "image": "alpine:latest",
+            "body": null,
+            "callbackUrl": "https://api.example.com/callback",
+            "restartPolicy": "on-failure:5"
+        }"#;
+
+        let Job::Docker(docker_job) = serde_json::from_str(json).unwrap();
+
+        assert_eq!(
+            docker_job.restart_policy,
+            Some(RestartPolicy::OnFailure(Some(5)))
+        );
+    }
+
+    #[test]
+    fn test_render_callback_url() {
+        let value = |name: &str| match name {
+            "id" => "123abc".to_string(),
+            _ => "running now".to_string(),
+        };
+        assert_eq!(
+            render_callback_url("https://api.example.com/jobs/{id}/{status}", value).unwrap(),
+            "https://api.example.com/jobs/123abc/running%20now"
+        );
+        assert_eq!(
+            render_callback_url("https://api.example.com/callback", value).unwrap(),
+            "https://api.example.com/callback"
+        );
+        let e = render_callback_url("https://api.example.com/{job}", value).unwrap_err();
+        assert!(e.to_string().contains("Unknown placeholder '{job}'"));
+        assert!(render_callback_url("https://api.example.com/{id", value).is_err());
+        assert!(validate_callback_url("https://api.example.com/{id}?s={status}").is_ok());
+        assert!(validate_callback_url("https://api.example.com/{}").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_docker_job_with_callback_method() {
+        let job_with_callback_method = |method: &str| {
+            serde_json::from_str::<Job>(&format!(
+                r#"{{
+                    "id": "123abc",
+                    "image": "alpine:latest",
+                    "body": null,
+                    "callbackUrl": "https://api.example.com/callback",
+                    "callbackMethod": "{}"
+                }}"#,
+                method
+            ))
+        };
+
+        let Job::Docker(docker_job) = job_with_callback_method("POST").unwrap();
+        assert_eq!(docker_job.callback_method, Some(CallbackMethod::Post));
+        let Job::Docker(docker_job) = job_with_callback_method("PATCH").unwrap();
+        assert_eq!(docker_job.callback_method, Some(CallbackMethod::Patch));
+        assert!(job_with_callback_method("DELETE").is_err());
+        assert!(job_with_callback_method("post").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_docker_job_with_gpus() {
+        let job_with_gpus = |gpus: &str| {
+            serde_json::from_str::<Job>(&format!(
+                r#"{{
+                    "id": "123abc",
+                    "image": "alpine:latest",
+                    "body": null,
+                    "callbackUrl": "https://api.example.com/callback",
+                    "gpus": {}
+                }}"#,
+                gpus
+            ))
+            .map(|Job::Docker(docker_job)| docker_job.gpus)
+        };
+
+        assert_eq!(job_with_gpus(r#""all""#).unwrap(), Some(Gpus::All));
+        assert_eq!(job_with_gpus("2").unwrap(), Some(Gpus::Count(2)));
+        assert_eq!(
+            job_with_gpus(r#"["0", "1"]"#).unwrap(),
+            Some(Gpus::DeviceIds(vec!["0".to_string(), "1".to_string()]))
+        );
+        assert!(job_with_gpus(r#""some""#).is_err());
+        assert!(job_with_gpus("0").is_err());
+        assert!(job_with_gpus("[]").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_docker_job_with_security_options() {
+        let json = r#"{
+            "id": "123abc",
+            "image": "alpine:latest",
+            "body": null,
+            "callbackUrl": "https://api.example.com/callback",
+            "readOnlyRootfs": true,
+            "capAdd": ["NET_BIND_SERVICE"],
+            "capDrop": ["ALL"],
+            "noNewPrivileges": true
+        }"#;
+
+        let Job::Docker(docker_job) = serde_json::from_str(json).unwrap();
+
+        assert_eq!(docker_job.read_only_rootfs, Some(true));
+        assert_eq!(
+            docker_job.cap_add,
+            Some(vec!["NET_BIND_SERVICE".to_string()])
+        );
+        assert_eq!(docker_job.cap_drop, Some(vec!["ALL".to_string()]));
+        assert_eq!(docker_job.no_new_privileges, Some(true));
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("64k").unwrap(), 64 * 1024);
+        assert_eq!(parse_size("512m").unwrap(), 512 * 1024 * 1024);
+        assert_eq!(parse_size("1G").unwrap(), 1024 * 1024 * 1024);
+        assert_eq!(parse_size("1gb").unwrap(), 1024 * 1024 * 1024);
+        assert!(parse_size("").is_err());
+        assert!(parse_size("m").is_err());
+        assert!(parse_size("1.5g").is_err());
+        assert!(parse_size("-1m").is_err());
+        assert!(parse_size("10x").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_docker_job_with_tmpfs() {
+        let json = r#"{
+            "id": "123abc",
+            "image": "alpine:latest",
+            "body": null,
+            "callbackUrl": "https://api.example.com/callback",
+            "tmpfs": { "/tmp": "64m", "/scratch": "1g" }
+        }"#;
+
+        let Job::Docker(docker_job) = serde_json::from_str(json).unwrap();
+
+        assert_eq!(
+            docker_job.tmpfs.unwrap().inner(),
+            &vec![
+                ("/tmp".to_string(), "64m".to_string()),
+                ("/scratch".to_string(), "1g".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deserialize_tmpfs_rejects_invalid_mounts() {
+        for tmpfs in [
+            r#"{ "/tmp": "64m", "/tmp": "1g" }"#,
+            r#"{ "tmp": "64m" }"#,
+            r#"{ "/tmp": "lots" }"#,
+        ] {
+            assert!(
+                serde_json::from_str::<TmpfsMounts>(tmpfs).is_err(),
+                "expected {} to be rejected",
+                tmpfs
+            );
+        }
+    }
+
+    #[test]
+    fn test_deserialize_docker_job_with_user() {
+        let json = r#"{
+            "id": "123abc",
+            "image": "alpine:latest",
+            "body": null,
+            "callbackUrl": "https://api.example.com/callback",
+            "user": "1000:1000"
+        }"#;
+
+        let Job::Docker(docker_job) = serde_json::from_str(json).unwrap();
+
+        assert_eq!(docker_job.user, Some("1000:1000".to_string()));
+    }
+
+    #[test]
+    fn test_deserialize_docker_job_with_timeout() {
+        let json = r#"{
+            "id": "123abc",
+            "image": "alpine:latest",
+            "body": null,
+            "callbackUrl": "https://api.example.com/callback",
+            "timeout": 3600000
+        }"#;
+
+        let Job::Docker(docker_job) = serde_json::from_str(json).unwrap();
+
+        assert_eq!(docker_job.timeout, Some(3_600_000));
+    }
+
+    #[test]
+    fn test_deserialize_docker_job_with_ulimits_and_shm_size() {
+        let json = r#"{
+            "id": "123abc",
+            "image": "alpine:latest",
+            "body": null,
+            "callbackUrl": "https://api.example.com/callback",
+            "ulimits": { "nofile": { "soft": 1024, "hard": 4096 } },
+            "shmSize": "256m"
+        }"#;
+
+        let Job::Docker(docker_job) = serde_json::from_str(json).unwrap();
+
+        assert_eq!(
+            docker_job.ulimits,
+            Some(HashMap::from([(
+                "nofile".to_string(),
+                Ulimit {
+                    soft: 1024,
+                    hard: 4096
+                }
+            )]))
+        );
+        assert_eq!(docker_job.shm_size, Some("256m".to_string()));
+    }
+
+    #[test]
+    fn test_deserialize_docker_job_with_dns() {
+        let json = r#"{
+            "id": "123abc",
+            "image": "alpine:latest",
+            "body": null,
+            "callbackUrl": "https://api.example.com/callback",
+            "dns": ["1.1.1.1", "8.8.8.8"],
+            "dnsSearch": ["example.com"]
+        }"#;
+
+        let Job::Docker(docker_job) = serde_json::from_str(json).unwrap();
+
+        assert_eq!(
+            docker_job.dns,
+            Some(vec!["1.1.1.1".to_string(), "8.8.8.8".to_string()])
+        );
+        assert_eq!(docker_job.dns_search, Some(vec!["example.com".to_string()]));
+    }
+
+    #[test]
+    fn test_deserialize_docker_job_with_hostname_and_cpuset() {
+        let json = r#"{
+            "id": "123abc",
+            "image": "alpine:latest",
+            "body": null,
+            "callbackUrl": "https://api.example.com/callback",
+            "hostname": "worker-1",
+            "cpuset": "0-3"
+        }"#;
+
+        let Job::Docker(docker_job) = serde_json::from_str(json).unwrap();
+
+        assert_eq!(docker_job.hostname, Some("worker-1".to_string()));
+        assert_eq!(docker_job.cpuset, Some("0-3".to_string()));
+    }
+
+    #[test]
+    fn test_deserialize_docker_job_with_extra_hosts() {
+        let json = r#"{
+            "id": "123abc",
+            "image": "alpine:latest",
+            "body": null,
+            "callbackUrl": "https://api.example.com/callback",
+            "extraHosts": ["db.internal:10.0.0.5", "host.docker.internal:host-gateway"]
+        }"#;
+
+        let Job::Docker(docker_job) = serde_json::from_str(json).unwrap();
+
+        assert_eq!(
+            docker_job.extra_hosts,
+            Some(vec![
+                "db.internal:10.0.0.5".to_string(),
+                "host.docker.internal:host-gateway".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_validate_job_id() {
+        assert!(validate_job_id("123abc").is_ok());
+        assert!(validate_job_id("job_123-ABC").is_ok());
+        assert!(validate_job_id("").is_err());
+        assert!(validate_job_id("123/abc").is_err());
+        assert!(validate_job_id("123 abc").is_err());
+        assert!(validate_job_id("../abc").is_err());
+        assert!(validate_job_id("abc?x=1").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_docker_job_with_entrypoint_and_working_dir() {
+        let json = r#"{
+            "id": "123abc",
+            "image": "alpine:latest",
+            "command": ["-c", "echo Hello world!"],
+            "entrypoint": ["/bin/sh"],
+            "workingDir": "/app",
+            "body": null,
+            "callbackUrl": "https://api.example.com/callback"
+        }"#;
+
+        let Job::Docker(docker_job) = serde_json::from_str(json).unwrap();
+
+        assert_eq!(
+            docker_job.command,
+            Some(vec!["-c".to_string(), "echo Hello world!".to_string()])
+        );
+        assert_eq!(docker_job.entrypoint, Some(vec!["/bin/sh".to_string()]));
+        assert_eq!(docker_job.working_dir, Some("/app".to_string()));
+    }
+
+    #[test]
+    fn test_ndjson_jobs() {
+        let mut ndjson = NdjsonJobs::default();
+        let jobs = ndjson.push(
+            br#"{"id": "job1", "image": "alpine", "body": {}, "callbackUrl": "http://localhost"}
+{"id": "jo"#,
+        );
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].as_ref().unwrap().id(), "job1");
+
+        let jobs = ndjson.push(
+            br#"b2", "image": "alpine", "body": {}, "callbackUrl": "http://localhost"}
+
+not json
+{"id": "job3", "image": "alpine", "body": {}, "callbackUrl": "http://localhost"}"#,
+        );
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].as_ref().unwrap().id(), "job2");
+        assert!(jobs[1].is_err());
+        assert_eq!(ndjson.finish().unwrap().unwrap().id(), "job3");
+    }
 }
@@ -0,0 +1,160 @@
+use std::io::Write;
+
+use log::{kv::Key, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// A logger which writes each record to stdout as a single-line JSON object.
+pub struct JsonLogger {
+    level: LevelFilter,
+}
+
+impl JsonLogger {
+    pub fn new(level: LevelFilter) -> Self {
+        JsonLogger { level }
+    }
+
+    /// Initialize the global logger with a `JsonLogger`.
+    pub fn init(level: LevelFilter) -> Result<(), log::SetLoggerError> {
+        log::set_max_level(level);
+        log::set_boxed_logger(Box::new(JsonLogger::new(level)))
+    }
+}
+
+/// Convert a `Record` into a JSON object containing the timestamp, level, target and message.
+/// If the record has a `job_id` key/value it is included as well.
+pub fn format_record(record: &Record) -> Value {
+    let timestamp = OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_default();
+    let mut value = json!({
+        "timestamp": timestamp,
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    });
+    if let Some(job_id) = record.key_values().get(Key::from("job_id")) {
+        value["job_id"] = Value::String(job_id.to_string());
+    }
+    value
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut stdout = std::io::stdout().lock();
+        let _ = writeln!(stdout, "{}", format_record(record));
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Resolve the log level from the `RUST_LOG` environment variable value or the `core.log_level`
+/// setting (in that order of precedence), falling back to `LevelFilter::Info` when neither is set.
+/// Only a plain level is accepted, not `env_logger` style per-target directives.
+pub fn resolve_level(
+    rust_log: Option<&str>,
+    log_level: Option<&str>,
+) -> anyhow::Result<LevelFilter> {
+    if let Some(s) = rust_log {
+        return s.trim().parse().map_err(|_| {
+            anyhow::anyhow!(
+                "Invalid log level '{}' in RUST_LOG environment variable, expected a plain level \
+                 e.g. 'debug'",
+                s
+            )
+        });
+    }
+    if let Some(s) = log_level {
+        return s
+            .trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid log level '{}' in core.log_level setting", s));
+    }
+    Ok(LevelFilter::Info)
+}
+
+/// Initialize the global logger using the given format.
+pub fn init(format: LogFormat, level: LevelFilter) -> anyhow::Result<()> {
+    match format {
+        LogFormat::Text => simplelog::SimpleLogger::init(level, simplelog::Config::default())?,
+        LogFormat::Json => JsonLogger::init(level)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use log::Level;
+
+    use super::*;
+
+    #[test]
+    fn test_format_record() {
+        let kvs = [("job_id", "123abc")];
+        let value = format_record(
+            &Record::builder()
+                .args(format_args!("Hello {}", "world"))
+                .level(Level::Info)
+                .target("foreman")
+                .key_values(&kvs)
+                .build(),
+        );
+
+        assert_eq!(value["level"], "INFO");
+        assert_eq!(value["target"], "foreman");
+        assert_eq!(value["message"], "Hello world");
+        assert_eq!(value["job_id"], "123abc");
+        assert!(value["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_format_record_without_job_id() {
+        let value = format_record(
+            &Record::builder()
+                .args(format_args!("Hello world"))
+                .level(Level::Error)
+                .target("foreman")
+                .build(),
+        );
+
+        assert_eq!(value["level"], "ERROR");
+        assert!(value.get("job_id").is_none());
+    }
+
+    #[test]
+    fn test_resolve_level() {
+        assert_eq!(resolve_level(None, None).unwrap(), LevelFilter::Info);
+        assert_eq!(
+            resolve_level(None, Some("debug")).unwrap(),
+            LevelFilter::Debug
+        );
+        assert_eq!(
+            resolve_level(Some("trace"), Some("debug")).unwrap(),
+            LevelFilter::Trace
+        );
+        assert_eq!(
+            resolve_level(Some("WARN"), None).unwrap(),
+            LevelFilter::Warn
+        );
+        assert!(resolve_level(Some("loud"), None).is_err());
+        assert!(resolve_level(None, Some("loud")).is_err());
+    }
+}
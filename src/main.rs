@@ -1,39 +1,77 @@
+mod access_log;
+mod check;
+mod circuit_breaker;
+mod cli;
+mod dead_letters;
 mod env;
 mod executors;
 mod job;
+mod logging;
+mod metrics;
 mod settings;
+mod sinks;
+mod sources;
+mod telemetry;
+mod tls;
 mod tracking;
 
 use std::{
+    net::SocketAddr,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc, LazyLock,
+        Arc, LazyLock, Mutex, RwLock,
     },
-    time::Duration,
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::{Ok, Result};
+use anyhow::{anyhow, Ok, Result};
 
 use axum::{
     body::Bytes,
-    extract::Path,
-    http::{HeaderMap, HeaderValue},
-    routing::{get, put},
+    extract::{
+        rejection::{BytesRejection, JsonRejection},
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        DefaultBodyLimit, Path,
+    },
+    http::{header::CONTENT_TYPE, HeaderMap, HeaderValue},
+    middleware,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    routing::{get, post, put},
     Json, Router,
 };
+use circuit_breaker::CircuitBreaker;
+use dead_letters::{DeadLetter, DeadLetters};
 use executors::{DockerExecutor, JobExecutor, JobExecutorCommand};
 use job::Job;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use metrics::{JobMetrics, PollMetrics};
 use reqwest::StatusCode;
 use serde_json::json;
-use settings::SETTINGS;
+use settings::{JobSourceKind, ResultSinkKind, StopOrder, SETTINGS};
+use sinks::{CallbackSink, FileSink, ResultSink, Sink};
+use sources::{HttpJobSource, JobSource};
 use tokio::{
     join,
-    sync::mpsc::{self},
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::{self, error::TrySendError},
+        oneshot,
+    },
+};
+use tracing::Instrument;
+use tracking::{
+    FailureReason, JobEvent, JobIdBuckets, JobStatus, JobStatusUpdate, JobTracker,
+    JobTrackerCommand, JobUpdate,
 };
-use tracking::{JobStatus, JobTracker, JobTrackerCommand};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+const DOCKER_CONNECT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const DOCKER_CONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// How long API requests wait for the job executor to accept or respond to a command.
+const EXECUTOR_TIMEOUT: Duration = Duration::from_secs(10);
 static USER_AGENT: LazyLock<String> = LazyLock::new(|| {
     format!(
         "foreman/{} ({}, {})",
@@ -43,54 +81,552 @@ static USER_AGENT: LazyLock<String> = LazyLock::new(|| {
     )
 });
 
+/// Track and execute a job received from the job source, acknowledging it.
+/// Returns `false` if the executor queue is full, or an error if the executor is no longer
+/// running, in which case the job is neither tracked nor acknowledged so will be fetched again.
+async fn dispatch_job(
+    job: Job,
+    job_executor_tx: &mpsc::Sender<JobExecutorCommand>,
+    job_tracker_tx: &mpsc::Sender<JobTrackerCommand>,
+    source: &impl JobSource,
+) -> Result<bool> {
+    info!(job_id = job.id(); "Got job: {:?}", job);
+    let job_id = job.id().to_owned();
+    // Reserve a slot in the executor queue before tracking the job. If the queue is full, back
+    // off until the next poll rather than blocking.
+    let permit = match job_executor_tx.try_reserve() {
+        std::result::Result::Ok(permit) => permit,
+        Err(TrySendError::Full(_)) => {
+            warn!(job_id = job_id.as_str(); "Executor queue is full, backing off");
+            return Ok(false);
+        }
+        Err(TrySendError::Closed(_)) => {
+            return Err(anyhow!("Job executor is not running"));
+        }
+    };
+    let span = telemetry::job_span(&job_id);
+    // Only execute jobs which weren't already tracked
+    let is_new = match tracking::insert_job(job.clone(), span.clone(), job_tracker_tx).await {
+        anyhow::Result::Ok(is_new) => is_new,
+        anyhow::Result::Err(e) => {
+            error!(job_id = job_id.as_str(); "Skipping job: {}", e);
+            return Ok(true);
+        }
+    };
+
+    if is_new {
+        permit.send(JobExecutorCommand::Execute {
+            job: Box::new(job),
+            span,
+        });
+    } else {
+        warn!(job_id = job_id.as_str(); "Skipping job as it is already tracked");
+    }
+
+    // Acknowledge duplicates too, in case an earlier acknowledgement was lost
+    if let Err(e) = source.ack(&job_id).await {
+        error!(job_id = job_id.as_str(); "Failed to acknowledge job: {}", e);
+    }
+    Ok(true)
+}
+
+/// Response for API requests which could not be served because the job tracker is unavailable.
+fn tracker_unavailable(e: anyhow::Error) -> (StatusCode, Json<serde_json::Value>) {
+    error!("Job tracker unavailable: {}", e);
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({ "error": "job tracker unavailable" })),
+    )
+}
+
+/// Response for API requests which could not be served because the job executor is unavailable.
+fn executor_unavailable(e: anyhow::Error) -> (StatusCode, Json<serde_json::Value>) {
+    error!("Job executor unavailable: {}", e);
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({ "error": "job executor unavailable" })),
+    )
+}
+
+/// The callback headers describing an update, as a job would send them to `PUT /job/:job_id`.
+fn update_headers(update: &JobUpdate) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    let status = format!("{:?}", update.status).to_lowercase();
+    headers.insert("x-foreman-job-status", HeaderValue::from_str(&status)?);
+    headers.insert(
+        "x-foreman-job-progress",
+        HeaderValue::from_str(&update.progress.to_string())?,
+    );
+    if let Some(exit_code) = update.exit_code {
+        headers.insert("x-foreman-job-exit-code", HeaderValue::from(exit_code));
+    }
+    if let Some(result) = &update.result {
+        headers.insert("x-foreman-job-result", HeaderValue::from_str(result)?);
+    }
+    Ok(headers)
+}
+
+/// Mark a job as failed, recording a dead letter for it.
+async fn fail_job(
+    job_id: &str,
+    reason: FailureReason,
+    error: &str,
+    job_tracker_tx: &mpsc::Sender<JobTrackerCommand>,
+    dead_letters: &DeadLetters,
+    job_metrics: &Mutex<JobMetrics>,
+) -> Result<()> {
+    tracking::set_failure_reason(job_id, reason, job_tracker_tx).await?;
+    tracking::update_job_status(job_id, JobStatus::Failed, None, job_tracker_tx).await?;
+    job_metrics.lock().unwrap().record_failure(reason);
+    let letter = match tracking::get_job(job_id, job_tracker_tx).await? {
+        Some(tracked_job) => DeadLetter::new(&tracked_job.lock().unwrap(), error),
+        None => return Ok(()),
+    };
+    if let Err(e) = dead_letters.record(letter).await {
+        error!(job_id = job_id; "Failed to record dead letter: {}", e);
+    }
+    Ok(())
+}
+
+/// Publish a job's update to the configured result sinks and, once published, record it in the
+/// job tracker.
+async fn forward_job_update(
+    sinks: &[Sink],
+    job_tracker_tx: &mpsc::Sender<JobTrackerCommand>,
+    job_id: &str,
+    update: JobUpdate,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, Json<serde_json::Value>) {
+    // Get the job object from the JobTracker
+    let job_opt = match tracking::get_job(job_id, job_tracker_tx).await {
+        std::result::Result::Ok(job_opt) => job_opt,
+        Err(e) => return tracker_unavailable(e),
+    };
+    let Some(tracked_job) = job_opt else {
+        return (StatusCode::NOT_FOUND, Json(json!({ "error": "not found" })));
+    };
+    let (docker_job, span) = {
+        let mut tracked_job = tracked_job.lock().unwrap();
+        // Protect foreman and the job's sinks from containers sending updates too quickly
+        if let Some(max_updates_per_second) = SETTINGS.core.max_updates_per_second {
+            if !tracked_job.record_update(max_updates_per_second, Instant::now()) {
+                warn!(
+                    job_id = job_id;
+                    "Rejecting update, job exceeded {} updates per second",
+                    max_updates_per_second
+                );
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    Json(json!({ "error": "too many updates" })),
+                );
+            }
+        }
+        let Job::Docker(docker_job) = tracked_job.inner();
+        (docker_job.clone(), tracked_job.span().clone())
+    };
+
+    // Publish the update to each of the configured sinks e.g., the job's callback URL
+    for sink in sinks {
+        if let Err(e) = sink
+            .publish(&docker_job, &span, &update, &headers, &body)
+            .await
+        {
+            let error_msg = format!("Failed to publish update: {}", e);
+            error!(job_id = job_id; "{}", error_msg);
+            let is_timeout = e
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(|e| e.is_timeout());
+            if is_timeout {
+                return (
+                    StatusCode::GATEWAY_TIMEOUT,
+                    Json(json!({ "error": error_msg })),
+                );
+            }
+            return (StatusCode::BAD_REQUEST, Json(json!({ "error": error_msg })));
+        }
+    }
+
+    let JobUpdate {
+        status,
+        progress,
+        exit_code,
+        result,
+    } = update;
+
+    // Record any reported exit code/result before updating the status, so it's
+    // included in the resulting job event
+    if exit_code.is_some() || result.is_some() {
+        if let Err(e) = tracking::set_job_result(job_id, exit_code, result, job_tracker_tx).await {
+            error!(job_id = job_id; "Error setting job result: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "failed to set job result" })),
+            );
+        }
+    }
+
+    // Update the job status in the JobTracker.
+    if let Err(e) =
+        tracking::update_job_status(job_id, status.clone(), Some(progress), job_tracker_tx).await
+    {
+        error!(job_id = job_id; "Error updating job status: {}", e);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "failed to update job status" })),
+        );
+    };
+
+    (
+        StatusCode::OK,
+        Json(json!({ "id": job_id, "status": status })),
+    )
+}
+
+/// Handle an update reported by a job's container via `PUT /job/:job_id`, read from the request's
+/// headers.
+async fn receive_job_update(
+    sinks: &[Sink],
+    job_tracker_tx: &mpsc::Sender<JobTrackerCommand>,
+    job_id: &str,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    info!(job_id = job_id; "Received PUT request for job ID: {}", job_id);
+    debug!("Headers: {:?}", headers);
+    // Surface body rejections (e.g. exceeding `core.max_callback_body`) as JSON
+    let body = match body {
+        std::result::Result::Ok(body) => body,
+        Err(rejection) => {
+            warn!(job_id = job_id; "Rejected PUT request body: {}", rejection);
+            return (
+                rejection.status(),
+                Json(json!({ "error": rejection.body_text() })),
+            );
+        }
+    };
+    let status: JobStatus = match headers.get("x-foreman-job-status") {
+        Some(hv) => match hv.to_str() {
+            std::result::Result::Ok(s) => match s.parse() {
+                std::result::Result::Ok(js) => js,
+                Err(e) => {
+                    let error_msg = format!("Invalid header x-foreman-job-status: {}", e);
+                    error!(job_id = job_id; "{}", error_msg);
+                    return (StatusCode::BAD_REQUEST, Json(json!({ "error": error_msg })));
+                }
+            },
+            Err(e) => {
+                let error_msg = format!("Failed to parse x-foreman-job-status header: {}", e);
+                error!(job_id = job_id; "{}", error_msg);
+                return (StatusCode::BAD_REQUEST, Json(json!({ "error": error_msg })));
+            }
+        },
+        None => {
+            let error_msg = "Missing x-foreman-job-status header";
+            error!(job_id = job_id; "{}", error_msg);
+            return (StatusCode::BAD_REQUEST, Json(json!({ "error": error_msg })));
+        }
+    };
+
+    let progress: f64 = match headers.get("x-foreman-job-progress") {
+        Some(hv) => {
+            let progress = hv
+                .to_str()
+                .map_err(anyhow::Error::from)
+                .and_then(|s| Ok(s.trim().parse::<f64>()?))
+                .and_then(|p| tracking::normalize_progress(p, SETTINGS.core.out_of_range_progress));
+            match progress {
+                std::result::Result::Ok(progress) => progress,
+                Err(e) => {
+                    let error_msg = format!("Invalid header x-foreman-job-progress: {}", e);
+                    error!(job_id = job_id; "{}", error_msg);
+                    return (StatusCode::BAD_REQUEST, Json(json!({ "error": error_msg })));
+                }
+            }
+        }
+        None => 0.0,
+    };
+
+    let exit_code: Option<i64> = match headers.get("x-foreman-job-exit-code") {
+        Some(hv) => match hv.to_str().ok().and_then(|s| s.trim().parse().ok()) {
+            Some(exit_code) => Some(exit_code),
+            None => {
+                let error_msg = "Invalid header x-foreman-job-exit-code: expected an integer";
+                error!(job_id = job_id; "{}", error_msg);
+                return (StatusCode::BAD_REQUEST, Json(json!({ "error": error_msg })));
+            }
+        },
+        None => None,
+    };
+    let result: Option<String> = headers
+        .get("x-foreman-job-result")
+        .and_then(|hv| hv.to_str().ok())
+        .map(str::to_owned);
+
+    let update = JobUpdate {
+        status,
+        progress,
+        exit_code,
+        result,
+    };
+    forward_job_update(sinks, job_tracker_tx, job_id, update, headers, body).await
+}
+
+/// Handle a batch of updates reported by a job's container via `PUT /job/:job_id/updates`.
+async fn receive_job_updates(
+    sinks: &[Sink],
+    job_tracker_tx: &mpsc::Sender<JobTrackerCommand>,
+    job_id: &str,
+    updates: Result<Json<Vec<JobUpdate>>, JsonRejection>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let updates = match updates {
+        std::result::Result::Ok(Json(updates)) => updates,
+        Err(rejection) => {
+            warn!(job_id = job_id; "Rejected batch of updates: {}", rejection);
+            return (
+                rejection.status(),
+                Json(json!({ "error": rejection.body_text() })),
+            );
+        }
+    };
+    info!(job_id = job_id; "Received batch of {} updates", updates.len());
+    // Only the latest update is forwarded to the callback URL
+    let update = match tracking::coalesce_updates(updates, SETTINGS.core.out_of_range_progress) {
+        std::result::Result::Ok(update) => update,
+        Err(e) => {
+            let error_msg = format!("Invalid batch of updates: {}", e);
+            error!(job_id = job_id; "{}", error_msg);
+            return (StatusCode::BAD_REQUEST, Json(json!({ "error": error_msg })));
+        }
+    };
+    let headers = match update_headers(&update) {
+        std::result::Result::Ok(headers) => headers,
+        Err(e) => {
+            let error_msg = format!("Invalid batch of updates: {}", e);
+            error!(job_id = job_id; "{}", error_msg);
+            return (StatusCode::BAD_REQUEST, Json(json!({ "error": error_msg })));
+        }
+    };
+    forward_job_update(sinks, job_tracker_tx, job_id, update, headers, Bytes::new()).await
+}
+
+/// Push status updates for a single job over a WebSocket, starting with its current status.
+/// The socket is closed once the job reaches a terminal status.
+async fn job_status_socket(
+    mut socket: WebSocket,
+    current: JobStatusUpdate,
+    mut rx: broadcast::Receiver<JobEvent>,
+) {
+    let job_id = current.job_id.clone();
+    let mut update = current;
+    loop {
+        let message = Message::Text(json!(update).to_string());
+        if socket.send(message).await.is_err() || update.status.is_terminal() {
+            break;
+        }
+        update = loop {
+            match rx.recv().await {
+                std::result::Result::Ok(event) if event.job_id == job_id => {
+                    break JobStatusUpdate::from(&event)
+                }
+                std::result::Result::Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => {
+                    let _ = socket.send(Message::Close(None)).await;
+                    return;
+                }
+            }
+        };
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize the logger.
-    simplelog::SimpleLogger::init(simplelog::LevelFilter::Info, simplelog::Config::default())?;
+    let args = cli::Args::parse(std::env::args().skip(1))?;
+    if args.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+    if args.version {
+        println!("foreman {}", VERSION);
+        return Ok(());
+    }
+    // Must be set before `SETTINGS` is first accessed
+    if let Some(config) = args.config {
+        settings::set_config_path(config)?;
+    }
+    match args.command {
+        Some(cli::Command::Check) => return check::run().await,
+        Some(cli::Command::Config) => {
+            // Secrets e.g., `core.token` are redacted when serialized
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&settings::Settings::new()?)?
+            );
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let started_at = Instant::now();
 
     // Load settings
     let settings = &*SETTINGS;
 
+    // Initialize the logger.
+    let log_level = logging::resolve_level(
+        std::env::var("RUST_LOG").ok().as_deref(),
+        settings.core.log_level.as_deref(),
+    )?;
+    logging::init(settings.core.log_format, log_level)?;
+
+    // Initialize OpenTelemetry tracing (if configured)
+    telemetry::init(&settings.telemetry)?;
+
     // Thread-safe boolean which indicates whether we are running.
     // This changes to false when a termination signal is received.
     let running = Arc::new(AtomicBool::new(true));
 
+    // Thread-safe boolean which indicates whether we have connected to Docker.
+    let docker_ready = Arc::new(AtomicBool::new(false));
+
+    // Thread-safe boolean which indicates whether Docker operations are paused after repeated
+    // failures, see `CircuitBreaker`.
+    let docker_degraded = Arc::new(AtomicBool::new(false));
+
+    // Thread-safe boolean which indicates whether we are draining i.e., no longer accepting new
+    // jobs and shutting down once all current jobs have finished.
+    let draining = Arc::new(AtomicBool::new(false));
+
+    // Thread-safe boolean which indicates whether polling for new jobs is paused.
+    let paused = Arc::new(AtomicBool::new(false));
+
+    // Metrics describing polls to the control server, exposed by the `/metrics` endpoint
+    let poll_metrics = Arc::new(Mutex::new(PollMetrics::default()));
+
     // Job executor channel
-    let (job_executor_tx, mut job_executor_rx) = mpsc::channel::<JobExecutorCommand>(32);
+    let (job_executor_tx, mut job_executor_rx) =
+        mpsc::channel::<JobExecutorCommand>(settings.core.executor_queue_size);
 
     // Job tracker channel
-    let (job_tracker_tx, mut job_tracker_rx) = mpsc::channel::<JobTrackerCommand>(32);
+    let (job_tracker_tx, mut job_tracker_rx) =
+        mpsc::channel::<JobTrackerCommand>(settings.core.tracker_queue_size);
+
+    // Job tracker, owned by the job tracking task
+    let mut job_tracker = JobTracker::new();
+    let slot_freed = job_tracker.slot_freed();
+    let events = job_tracker.events();
+
+    // HTTP client shared by the poller and the callback handler so connections are reused.
+    // Headers and timeouts are set per request so e.g., the control server token is never sent
+    // to callback URLs. Compressed responses (e.g. large batches of jobs) are decompressed
+    // transparently.
+    let http_client = reqwest::ClientBuilder::new()
+        .user_agent(&*USER_AGENT)
+        .gzip(true)
+        .brotli(true)
+        .build()?;
+
+    // Sinks which job updates are published to, in order
+    let job_metrics = Arc::new(Mutex::new(JobMetrics::default()));
+    let dead_letters = Arc::new(DeadLetters::new(
+        settings.core.dead_letter_capacity,
+        settings.core.dead_letter_file.clone(),
+    ));
+
+    let result_sinks: Arc<Vec<Sink>> = Arc::new(
+        settings
+            .core
+            .result_sinks
+            .iter()
+            .map(|kind| match kind {
+                ResultSinkKind::Callback => {
+                    Ok(Sink::Callback(CallbackSink::new(http_client.clone())))
+                }
+                // Checked when loading settings, but fail start-up rather than panicking
+                ResultSinkKind::File => match &settings.core.result_file {
+                    Some(result_file) => Ok(Sink::File(FileSink::new(result_file.clone()))),
+                    None => Err(anyhow!(
+                        "core.result_file must be set to use the file result sink"
+                    )),
+                },
+            })
+            .collect::<Result<_>>()?,
+    );
+
+    // Control server token. When read from `core.token_file` the file is periodically re-read
+    // so the token can be rotated without a restart.
+    let token = Arc::new(RwLock::new(settings.core.token.clone()));
+    if let Some(token_file) = &settings.core.token_file {
+        let token2 = token.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_millis(settings.core.token_refresh_interval))
+                    .await;
+                match settings::read_token_file(token_file) {
+                    std::result::Result::Ok(new_token) => {
+                        let mut token = token2.write().unwrap();
+                        if *token != new_token {
+                            info!("Control server token updated from {}", token_file.display());
+                            *token = new_token;
+                        }
+                    }
+                    Err(e) => warn!("Keeping previous control server token: {}", e),
+                }
+            }
+        });
+    }
 
     // Control server poller
     let running2 = running.clone();
+    let draining2 = draining.clone();
+    let paused2 = paused.clone();
+    let token3 = token.clone();
+    let http_client2 = http_client.clone();
+    let docker_ready2 = docker_ready.clone();
+    let docker_degraded3 = docker_degraded.clone();
     let job_tracker_tx2 = job_tracker_tx.clone();
     let job_executor_tx2 = job_executor_tx.clone();
+    let poll_metrics2 = poll_metrics.clone();
     let control_server_poller_task = tokio::spawn(async move {
-        // Set default headers
-        let mut default_headers = HeaderMap::new();
-        if let Some(labels) = &settings.core.labels {
-            let labels_string: String = labels.into();
-            default_headers.insert(
-                "x-foreman-labels",
-                labels_string
-                    .parse()
-                    .expect("Failed to parse labels into header value"),
-            );
-        }
-        // Configure the HTTP client
-        let http_client = reqwest::ClientBuilder::new()
-            .timeout(Duration::from_millis(settings.core.poll_timeout.into()))
-            .user_agent(&*USER_AGENT)
-            .default_headers(default_headers)
-            .build()
-            .unwrap();
+        let mut source = match settings.core.source {
+            JobSourceKind::Http => HttpJobSource::new(http_client2, token3, poll_metrics2),
+        };
         loop {
-            if !running2.load(Ordering::SeqCst) {
+            if !running2.load(Ordering::SeqCst) || draining2.load(Ordering::SeqCst) {
                 info!("Stopping poller task");
                 break;
             }
 
-            // If we've reached our maximum concurrent jobs, sleep before polling again
+            // Don't accept new jobs while paused. Existing jobs continue to be tracked as normal.
+            if paused2.load(Ordering::SeqCst) {
+                tokio::time::sleep(tokio::time::Duration::from_millis(
+                    settings.core.poll_frequency.into(),
+                ))
+                .await;
+                continue;
+            }
+
+            // Don't accept jobs until we are connected to Docker and it's healthy
+            if docker_degraded3.load(Ordering::SeqCst) {
+                info!("Docker is degraded, waiting before polling");
+                tokio::time::sleep(tokio::time::Duration::from_millis(
+                    settings.core.poll_frequency.into(),
+                ))
+                .await;
+                continue;
+            }
+            if !docker_ready2.load(Ordering::SeqCst) {
+                info!("Waiting for Docker connection before polling");
+                tokio::time::sleep(tokio::time::Duration::from_millis(
+                    settings.core.poll_frequency.into(),
+                ))
+                .await;
+                continue;
+            }
+
+            // If we've reached our maximum concurrent jobs, wait until a slot is freed (or a
+            // while has passed) before polling again
             let running_jobs_count = tracking::count_running_jobs(&job_tracker_tx2)
                 .await
                 .unwrap_or_default();
@@ -99,46 +635,74 @@ async fn main() -> Result<()> {
                     "Reached maximum concurrent jobs ({}), waiting a bit before polling again",
                     settings.core.max_concurrent_jobs
                 );
-                tokio::time::sleep(tokio::time::Duration::from_millis(
-                    settings.core.poll_frequency.into(),
-                ))
-                .await;
+                tokio::select! {
+                    _ = tokio::time::sleep(tokio::time::Duration::from_millis(
+                        settings.core.poll_frequency.into(),
+                    )) => {}
+                    _ = slot_freed.notified() => {}
+                }
                 continue;
             }
 
-            // Poll control server for jobs
-            let jobs_result: anyhow::Result<Vec<Job>> = async {
-                let jobs = http_client
-                    .get(&settings.core.url)
-                    .header("Authorization", format!("Bearer {}", settings.core.token))
-                    .send()
-                    .await?
-                    .json::<Vec<Job>>()
-                    .await?;
-                Ok(jobs)
-            }
-            .await;
-
-            match jobs_result {
+            let free_slots = settings
+                .core
+                .max_concurrent_jobs
+                .saturating_sub(running_jobs_count as u64);
+            // When long polling, poll again immediately unless the poll failed or jobs were turned
+            // away
+            let mut poll_immediately = true;
+            match source.fetch(free_slots).await {
                 anyhow::Result::Ok(jobs) => {
-                    for job in jobs {
-                        info!("Got job: {:?}", job);
-                        job_tracker_tx2
-                            .send(JobTrackerCommand::Insert { job: job.clone() })
-                            .await
-                            .expect("Failed to send job to tracker channel");
-
-                        job_executor_tx2
-                            .send(JobExecutorCommand::Execute { job })
-                            .await
-                            .expect("Failed to send job to executor channel");
+                    let mut accepted = 0;
+                    let mut batch = Some(jobs);
+                    'batch: while let Some(jobs) = batch {
+                        for job in jobs {
+                            // Jobs beyond the limit aren't tracked or acknowledged so will be
+                            // fetched again
+                            if settings
+                                .core
+                                .max_jobs_per_poll
+                                .is_some_and(|max_jobs_per_poll| accepted >= max_jobs_per_poll)
+                            {
+                                warn!("Accepted {} jobs, ignoring the rest of the batch", accepted);
+                                poll_immediately = false;
+                                break 'batch;
+                            }
+                            match dispatch_job(job, &job_executor_tx2, &job_tracker_tx2, &source)
+                                .await
+                            {
+                                std::result::Result::Ok(true) => {}
+                                std::result::Result::Ok(false) => {
+                                    poll_immediately = false;
+                                    break 'batch;
+                                }
+                                Err(e) => {
+                                    error!("Failed to dispatch job: {}", e);
+                                    poll_immediately = false;
+                                    break 'batch;
+                                }
+                            }
+                            accepted += 1;
+                        }
+                        batch = match source.fetch_more().await {
+                            anyhow::Result::Ok(batch) => batch,
+                            anyhow::Result::Err(e) => {
+                                error!("Error fetching jobs from job source: {}", e);
+                                poll_immediately = false;
+                                None
+                            }
+                        };
                     }
                 }
                 anyhow::Result::Err(e) => {
-                    error!("Error fetching job from control server: {}", e)
+                    error!("Error fetching job from control server: {}", e);
+                    poll_immediately = false;
                 }
             };
 
+            if settings.core.long_poll && poll_immediately {
+                continue;
+            }
             tokio::time::sleep(tokio::time::Duration::from_millis(
                 settings.core.poll_frequency.into(),
             ))
@@ -147,46 +711,276 @@ async fn main() -> Result<()> {
     });
 
     // Manager task with exclusive access to Docker
-    let job_manager_task = tokio::spawn(async move {
-        let mut executor = DockerExecutor::new()
-            .await
-            .expect("Failed to create Docker executor");
+    let docker_ready3 = docker_ready.clone();
+    let docker_degraded2 = docker_degraded.clone();
+    let job_tracker_tx9 = job_tracker_tx.clone();
+    let dead_letters2 = dead_letters.clone();
+    let job_metrics2 = job_metrics.clone();
+    let job_executor_tx6 = job_executor_tx.clone();
+    tokio::spawn(async move {
+        // Connect to Docker, retrying with exponential backoff until successful
+        let mut backoff = DOCKER_CONNECT_INITIAL_BACKOFF;
+        let mut executor = loop {
+            match DockerExecutor::new().await {
+                std::result::Result::Ok(executor) => break executor,
+                Err(e) => {
+                    error!(
+                        "Failed to connect to Docker, retrying in {}s: {}",
+                        backoff.as_secs(),
+                        e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(DOCKER_CONNECT_MAX_BACKOFF);
+                }
+            }
+        };
+        info!("Connected to Docker");
+        docker_ready3.store(true, Ordering::SeqCst);
 
+        let mut circuit_breaker = CircuitBreaker::new(
+            settings.docker.circuit_breaker_threshold,
+            Duration::from_millis(settings.docker.circuit_breaker_cooldown),
+        );
         while let Some(command) = job_executor_rx.recv().await {
-            match command {
-                JobExecutorCommand::Execute { job } => {
-                    if let Err(e) = executor.execute(job).await {
-                        error!("Error executing job: {}", e)
+            // While the circuit breaker is open, hold commands until Docker responds to a probe
+            while let Some(open_until) = circuit_breaker.open_until() {
+                tokio::time::sleep_until(open_until.into()).await;
+                match executor.ping().await {
+                    std::result::Result::Ok(_) => {
+                        info!("Docker is responding, resuming Docker operations");
+                        circuit_breaker.record_success();
+                        docker_degraded2.store(false, Ordering::SeqCst);
+                    }
+                    Err(e) => {
+                        warn!("Docker is still not responding: {}", e);
+                        circuit_breaker.record_failure();
+                    }
+                }
+            }
+
+            // Whether the command succeeded, if it reflects the health of Docker
+            let succeeded = match command {
+                JobExecutorCommand::Execute { job, span } => {
+                    // Prepare the job (e.g. pull it's image) concurrently, so a slow pull doesn't
+                    // hold up other Docker operations. Once prepared, the job is started by the
+                    // manager via a `Start` command.
+                    let executor = executor.clone();
+                    let job_executor_tx = job_executor_tx6.clone();
+                    let job_tracker_tx = job_tracker_tx9.clone();
+                    let dead_letters = dead_letters2.clone();
+                    let job_metrics = job_metrics2.clone();
+                    tokio::spawn(async move {
+                        let job_id = job.id().to_owned();
+                        match executor.prepare(&job).instrument(span.clone()).await {
+                            std::result::Result::Ok(_) => {
+                                let _ = job_executor_tx
+                                    .send(JobExecutorCommand::Start { job, span })
+                                    .await;
+                            }
+                            Err(e) => {
+                                error!(job_id = job_id.as_str(); "Error preparing job: {}", e);
+                                let error = format!("Error preparing job: {}", e);
+                                if let Err(e) = fail_job(
+                                    &job_id,
+                                    FailureReason::PrepareFailed,
+                                    &error,
+                                    &job_tracker_tx,
+                                    &dead_letters,
+                                    &job_metrics,
+                                )
+                                .await
+                                {
+                                    error!(
+                                        job_id = job_id.as_str();
+                                        "Failed to update job status: {}",
+                                        e
+                                    );
+                                }
+                            }
+                        }
+                    });
+                    None
+                }
+                JobExecutorCommand::Start { job, span } => {
+                    let job_id = job.id().to_owned();
+                    // The job may have been stopped or removed while it was being prepared
+                    let pending = match tracking::get_job(&job_id, &job_tracker_tx9).await {
+                        std::result::Result::Ok(Some(tracked_job)) => {
+                            *tracked_job.lock().unwrap().status() == JobStatus::Pending
+                        }
+                        std::result::Result::Ok(None) => false,
+                        Err(e) => {
+                            error!(job_id = job_id.as_str(); "Failed to get job: {}", e);
+                            false
+                        }
+                    };
+                    if !pending {
+                        info!(
+                            job_id = job_id.as_str();
+                            "Job is no longer pending, not starting it"
+                        );
+                        continue;
+                    }
+                    let result = executor.start(*job).instrument(span).await;
+                    if result.is_ok() {
+                        // Distinguishes time spent queued and pulling from time spent executing
+                        if let Err(e) = tracking::set_container_started(
+                            &job_id,
+                            SystemTime::now(),
+                            &job_tracker_tx9,
+                        )
+                        .await
+                        {
+                            error!(
+                                job_id = job_id.as_str();
+                                "Failed to record container start: {}",
+                                e
+                            );
+                        }
+                    }
+                    if let Err(e) = &result {
+                        error!(job_id = job_id.as_str(); "Error executing job: {}", e);
+                        // Clean up any partially created container and mark the job as failed so
+                        // it no longer counts towards the concurrency limit
+                        if let Err(e) = executor.remove(&job_id, true).await {
+                            error!(job_id = job_id.as_str(); "Error removing failed job: {}", e);
+                        }
+                        let error = format!("Error executing job: {}", e);
+                        if let Err(e) = fail_job(
+                            &job_id,
+                            FailureReason::StartFailed,
+                            &error,
+                            &job_tracker_tx9,
+                            &dead_letters2,
+                            &job_metrics2,
+                        )
+                        .await
+                        {
+                            error!(job_id = job_id.as_str(); "Failed to update job status: {}", e);
+                        }
                     }
+                    Some(result.is_ok())
+                }
+                JobExecutorCommand::Validate { job, resp } => {
+                    let result = executor.validate(*job).await;
+                    // The requester may have given up waiting
+                    let _ = resp.send(result);
+                    None
                 }
                 JobExecutorCommand::Stop { job_id } => {
-                    if let Err(e) = executor.stop(&job_id).await {
-                        error!("Error stopping job: {}", e)
+                    let result = executor.stop(&job_id, None).await;
+                    if let Err(e) = &result {
+                        error!(job_id = job_id.as_str(); "Error stopping job: {}", e)
+                    }
+                    Some(result.is_ok())
+                }
+                JobExecutorCommand::Terminate {
+                    job_id,
+                    deadline,
+                    resp,
+                } => {
+                    let mut executor = executor.clone();
+                    tokio::spawn(async move {
+                        let grace_period = Some(settings.core.shutdown_grace_period);
+                        let stop = executor.stop(&job_id, grace_period);
+                        match tokio::time::timeout_at(deadline.into(), stop).await {
+                            std::result::Result::Ok(std::result::Result::Ok(_)) => {}
+                            std::result::Result::Ok(Err(e)) => {
+                                error!(job_id = job_id.as_str(); "Error stopping job: {}", e)
+                            }
+                            Err(_) => {
+                                warn!(
+                                    job_id = job_id.as_str();
+                                    "Job did not stop within core.shutdown_timeout, killing it"
+                                );
+                                if let Err(e) = executor.remove(&job_id, true).await {
+                                    error!(job_id = job_id.as_str(); "Error killing job: {}", e)
+                                }
+                            }
+                        }
+                        let _ = resp.send(());
+                    });
+                    None
+                }
+                JobExecutorCommand::Remove { job_id, force } => {
+                    let result = executor.remove(&job_id, force).await;
+                    if let Err(e) = &result {
+                        error!(job_id = job_id.as_str(); "Error removing job: {}", e)
                     }
+                    Some(result.is_ok())
+                }
+                JobExecutorCommand::Stats { job_id, resp } => {
+                    let mut executor = executor.clone();
+                    // Sampling takes a while, so is done without holding up other commands
+                    tokio::spawn(async move {
+                        let result = executor.stats(&job_id).await;
+                        // The requester may have given up waiting
+                        let _ = resp.send(result);
+                    });
+                    None
+                }
+                JobExecutorCommand::Exits { job_ids, resp } => {
+                    let executor = executor.clone();
+                    // Containers are inspected concurrently, without holding up other commands
+                    tokio::spawn(async move {
+                        let results = futures::future::join_all(
+                            job_ids.iter().map(|job_id| executor.exit(job_id)),
+                        )
+                        .await;
+                        let mut exits = Vec::new();
+                        for (job_id, result) in job_ids.into_iter().zip(results) {
+                            match result {
+                                std::result::Result::Ok(Some(exit)) => exits.push((job_id, exit)),
+                                std::result::Result::Ok(None) => {}
+                                Err(e) => {
+                                    warn!(
+                                        job_id = job_id.as_str();
+                                        "Failed to inspect job's container: {}",
+                                        e
+                                    )
+                                }
+                            }
+                        }
+                        let _ = resp.send(exits);
+                    });
+                    None
+                }
+                JobExecutorCommand::Flush { resp } => {
+                    let _ = resp.send(());
+                    None
                 }
-                JobExecutorCommand::Remove { job_id } => {
-                    if let Err(e) = executor.remove(&job_id).await {
-                        error!("Error removing job: {}", e)
+            };
+            match succeeded {
+                Some(true) => circuit_breaker.record_success(),
+                Some(false) => {
+                    let opened = circuit_breaker.record_failure();
+                    if opened {
+                        warn!(
+                            "Docker operations failed {} times in a row, pausing them for {}ms",
+                            settings.docker.circuit_breaker_threshold,
+                            settings.docker.circuit_breaker_cooldown
+                        );
+                        docker_degraded2.store(true, Ordering::SeqCst);
                     }
                 }
+                None => {}
             }
         }
     });
 
     // Job tracking task for managing job state
-    let job_tracking_task = tokio::spawn(async move {
-        let mut job_tracker = JobTracker::new();
+    tokio::spawn(async move {
         loop {
             // Process commands received from the job tracker channel
             if let Some(command) = job_tracker_rx.recv().await {
+                // Responses are dropped if the sender gave up waiting (e.g. a cancelled request)
                 match command {
-                    JobTrackerCommand::Insert { job } => {
-                        job_tracker.insert(job);
+                    JobTrackerCommand::Insert { job, span, resp } => {
+                        let _ = resp.send(job_tracker.insert(*job, span));
                     }
                     JobTrackerCommand::GetJob { job_id, resp } => {
                         let result = job_tracker.get_job(&job_id).cloned();
-                        resp.send(Ok(result))
-                            .expect("Failed to send has job response over channel");
+                        let _ = resp.send(Ok(result));
                     }
                     JobTrackerCommand::UpdateStatus {
                         job_id,
@@ -195,38 +989,40 @@ async fn main() -> Result<()> {
                         resp,
                     } => {
                         let result = job_tracker.update_status(&job_id, status, progress);
-                        resp.send(result)
-                            .expect("Failed to send update status response over channel");
-                    }
-                    JobTrackerCommand::GetRunningJobIds { resp } => {
-                        let running_job_ids = job_tracker.get_running_job_ids();
-                        resp.send(Ok(running_job_ids))
-                            .expect("Failed to send running job ids response over channel");
-                    }
-                    JobTrackerCommand::GetStoppedJobIds { resp } => {
-                        let stopped_job_ids = job_tracker.get_stopped_job_ids();
-                        resp.send(Ok(stopped_job_ids))
-                            .expect("Failed to send stopped job ids response over channel");
-                    }
-                    JobTrackerCommand::GetCompletedJobIds { resp } => {
-                        let completed_job_ids = job_tracker.get_completed_job_ids();
-                        resp.send(Ok(completed_job_ids))
-                            .expect("Failed to send completed job ids response over channel");
-                    }
-                    JobTrackerCommand::GetTimedOutJobIds { resp } => {
-                        let timed_out_job_ids = job_tracker.get_timed_out_job_ids();
-                        resp.send(Ok(timed_out_job_ids))
-                            .expect("Failed to send timed out job ids response over channel");
-                    }
-                    JobTrackerCommand::GetStoppedAndExpiredJobIds { resp } => {
-                        let stopped_job_ids = job_tracker.get_stopped_and_expired_job_ids();
-                        resp.send(Ok(stopped_job_ids))
-                            .expect("Failed to send stopped job ids response over channel");
+                        let _ = resp.send(result);
+                    }
+                    JobTrackerCommand::SetResult {
+                        job_id,
+                        exit_code,
+                        result,
+                        resp,
+                    } => {
+                        let result = job_tracker.set_result(&job_id, exit_code, result);
+                        let _ = resp.send(result);
+                    }
+                    JobTrackerCommand::SetFailureReason {
+                        job_id,
+                        reason,
+                        resp,
+                    } => {
+                        let result = job_tracker.set_failure_reason(&job_id, reason);
+                        let _ = resp.send(result);
+                    }
+                    JobTrackerCommand::SetContainerStarted { job_id, time, resp } => {
+                        let result = job_tracker.set_container_started(&job_id, time);
+                        let _ = resp.send(result);
+                    }
+                    JobTrackerCommand::GetJobIdBuckets { resp } => {
+                        let job_id_buckets = job_tracker.get_job_id_buckets();
+                        let _ = resp.send(Ok(job_id_buckets));
                     }
                     JobTrackerCommand::CountRunningJobs { resp } => {
                         let count = job_tracker.count_running_jobs();
-                        resp.send(Ok(count))
-                            .expect("Failed to send running job count response over channel");
+                        let _ = resp.send(Ok(count));
+                    }
+                    JobTrackerCommand::RemoveFinishedJobs { retention, resp } => {
+                        let removed = job_tracker.remove_finished_jobs(retention);
+                        let _ = resp.send(Ok(removed));
                     }
                 }
             }
@@ -235,287 +1031,916 @@ async fn main() -> Result<()> {
 
     // Job lifecycle task coordinates between job tracker and job executor
     let running3 = running.clone();
+    let draining3 = draining.clone();
     let job_tracker_tx3 = job_tracker_tx.clone();
+    let dead_letters3 = dead_letters.clone();
+    let job_metrics3 = job_metrics.clone();
     let job_executor_tx3 = job_executor_tx.clone();
-    let job_lifecycle_task =
-        tokio::spawn(async move {
-            loop {
-                // Send stop command to the job executor for any completed jobs
-                let completed_job_ids = tracking::get_completed_job_ids(&job_tracker_tx3).await;
-                if let Some(completed_job_ids) = completed_job_ids {
-                    for job_id in completed_job_ids {
-                        info!("Sending 'Stop' command for completed job: {}", job_id);
-                        let command = JobExecutorCommand::Stop {
-                            job_id: job_id.clone(),
-                        };
-                        job_executor_tx3.send(command).await.expect(
-                            "Failed to send stop command to job executor for completed job ",
+    let job_lifecycle_task = tokio::spawn(async move {
+        // Deadline by which running jobs must have stopped during shutdown
+        let mut shutdown_deadline: Option<Instant> = None;
+        loop {
+            // Fetch the IDs of all jobs we may need to act on in a single round-trip
+            let JobIdBuckets {
+                pending: pending_job_ids,
+                completed: completed_job_ids,
+                timed_out: timed_out_job_ids,
+                stopped_and_expired: stopped_and_expired_job_ids,
+                pending_and_expired: pending_and_expired_job_ids,
+                lifetime_exceeded: lifetime_exceeded_job_ids,
+                running: running_job_ids,
+                stopped: stopped_job_ids,
+            } = tracking::get_job_id_buckets(&job_tracker_tx3)
+                .await
+                .unwrap_or_default();
+            // Stop tracking jobs which finished longer than the retention window ago
+            match tracking::remove_finished_jobs(
+                Duration::from_millis(settings.core.finished_job_retention),
+                &job_tracker_tx3,
+            )
+            .await
+            {
+                std::result::Result::Ok(removed_job_ids) => {
+                    for job_id in removed_job_ids {
+                        debug!(
+                            job_id = job_id.as_str();
+                            "Stopped tracking finished job: {}",
+                            job_id
                         );
-                        tracking::update_job_status(
-                            &job_id,
-                            JobStatus::Stopped,
-                            None,
-                            &job_tracker_tx3,
-                        )
-                        .await
-                        .expect("Failed to update job status to 'stopped' for completed job");
                     }
                 }
-                // Send stop command to the job executor for any timed-out jobs
-                let timed_out_job_ids = tracking::get_timed_out_job_ids(&job_tracker_tx3).await;
-                if let Some(timed_out_job_ids) = timed_out_job_ids {
-                    for job_id in timed_out_job_ids {
-                        info!("Sending 'Stop' command for timed-out job: {}", job_id);
-                        let command = JobExecutorCommand::Stop {
-                            job_id: job_id.clone(),
-                        };
-                        job_executor_tx3.send(command).await.expect(
-                            "Failed to send 'stop' command to job executor for timed-out job",
+                Err(e) => error!("Failed to remove finished jobs: {}", e),
+            }
+            let all_jobs_finished = pending_job_ids.is_empty()
+                && completed_job_ids.is_empty()
+                && running_job_ids.is_empty()
+                && stopped_job_ids.is_empty();
+
+            // Send stop command to the job executor for any completed jobs, or remove them straight
+            // away if configured to
+            for job_id in completed_job_ids {
+                if settings.core.remove_on_complete_immediately {
+                    info!(
+                        job_id = job_id.as_str();
+                        "Sending 'remove' command for completed job: {}",
+                        job_id
+                    );
+                    // Forced, as the container may not have exited yet
+                    let command = JobExecutorCommand::Remove {
+                        job_id: job_id.clone(),
+                        force: true,
+                    };
+                    if let Err(e) = job_executor_tx3.send(command).await {
+                        error!(
+                            job_id = job_id.as_str();
+                            "Failed to send 'remove' command to job executor for completed job: {}",
+                            e
                         );
-                        tracking::update_job_status(
-                            &job_id,
-                            JobStatus::Stopped,
-                            None,
-                            &job_tracker_tx3,
-                        )
-                        .await
-                        .expect("Failed to update job status to 'stopped' for timed-out job");
+                        continue;
                     }
-                }
-                // Send remove command to the job executor for any stopped and expired jobs
-                let stopped_job_ids =
-                    tracking::get_stopped_and_expired_job_ids(&job_tracker_tx3).await;
-                if let Some(stopped_job_ids) = stopped_job_ids {
-                    for job_id in stopped_job_ids {
-                        info!("Sending 'remove' command for stopped job: {}", job_id);
-                        let command = JobExecutorCommand::Remove {
-                            job_id: job_id.clone(),
-                        };
-                        job_executor_tx3.send(command).await.expect(
-                            "Failed to send 'remove' command to job executor for stopped job",
+                    if let Err(e) = tracking::update_job_status(
+                        &job_id,
+                        JobStatus::Finished,
+                        None,
+                        &job_tracker_tx3,
+                    )
+                    .await
+                    {
+                        error!(
+                            job_id = job_id.as_str();
+                            "Failed to update job status to 'finished' for completed job: {}",
+                            e
                         );
-                        tracking::update_job_status(
-                            &job_id,
-                            JobStatus::Finished,
-                            None,
-                            &job_tracker_tx3,
-                        )
+                    }
+                    continue;
+                }
+                info!(
+                    job_id = job_id.as_str();
+                    "Sending 'Stop' command for completed job: {}",
+                    job_id
+                );
+                let command = JobExecutorCommand::Stop {
+                    job_id: job_id.clone(),
+                };
+                if let Err(e) = job_executor_tx3.send(command).await {
+                    error!(
+                        job_id = job_id.as_str();
+                        "Failed to send 'stop' command for completed job: {}",
+                        e
+                    );
+                    continue;
+                }
+                if let Err(e) =
+                    tracking::update_job_status(&job_id, JobStatus::Stopped, None, &job_tracker_tx3)
+                        .await
+                {
+                    error!(
+                        job_id = job_id.as_str();
+                        "Failed to update job status to 'stopped' for completed job: {}",
+                        e
+                    );
+                    continue;
+                }
+            }
+            // Send stop command to the job executor for any timed-out jobs
+            for job_id in &timed_out_job_ids {
+                info!(
+                    job_id = job_id.as_str();
+                    "Sending 'Stop' command for timed-out job: {}",
+                    job_id
+                );
+                let command = JobExecutorCommand::Stop {
+                    job_id: job_id.clone(),
+                };
+                if let Err(e) = job_executor_tx3.send(command).await {
+                    error!(
+                        job_id = job_id.as_str();
+                        "Failed to send 'stop' command for timed-out job: {}",
+                        e
+                    );
+                    continue;
+                }
+                if let Err(e) =
+                    tracking::update_job_status(job_id, JobStatus::Stopped, None, &job_tracker_tx3)
                         .await
-                        .expect("Failed to update job status to 'finished' for stopped job");
+                {
+                    error!(
+                        job_id = job_id.as_str();
+                        "Failed to update job status to 'stopped' for timed-out job: {}",
+                        e
+                    );
+                    continue;
+                }
+            }
+            // Send stop and remove commands to the job executor for any jobs which never started
+            for job_id in &pending_and_expired_job_ids {
+                info!(
+                    job_id = job_id.as_str();
+                    "Sending 'Stop' and 'remove' commands for pending job which didn't start: {}",
+                    job_id
+                );
+                for command in [
+                    JobExecutorCommand::Stop {
+                        job_id: job_id.clone(),
+                    },
+                    JobExecutorCommand::Remove {
+                        job_id: job_id.clone(),
+                        force: settings.core.force_remove,
+                    },
+                ] {
+                    if let Err(e) = job_executor_tx3.send(command).await {
+                        error!(
+                            job_id = job_id.as_str();
+                            "Failed to send command to job executor for pending job: {}",
+                            e
+                        );
                     }
                 }
-
-                if !running3.load(Ordering::SeqCst) {
-                    // Stop any running jobs
-                    let running_job_ids = tracking::get_running_job_ids(&job_tracker_tx3)
+                if let Err(e) = fail_job(
+                    job_id,
+                    FailureReason::StartTimeout,
+                    "Job was not fetched by it's container within core.job_start_timeout",
+                    &job_tracker_tx3,
+                    &dead_letters3,
+                    &job_metrics3,
+                )
+                .await
+                {
+                    error!(
+                        job_id = job_id.as_str();
+                        "Failed to update job status to 'failed' for pending job: {}",
+                        e
+                    );
+                    continue;
+                }
+            }
+            // Send stop command to the job executor for any jobs which exceeded their lifetime
+            for job_id in &lifetime_exceeded_job_ids {
+                info!(
+                    job_id = job_id.as_str();
+                    "Sending 'Stop' command for job which exceeded it's maximum lifetime: {}",
+                    job_id
+                );
+                let command = JobExecutorCommand::Stop {
+                    job_id: job_id.clone(),
+                };
+                if let Err(e) = job_executor_tx3.send(command).await {
+                    error!(
+                        job_id = job_id.as_str();
+                        "Failed to send 'stop' command for lifetime exceeded job: {}",
+                        e
+                    );
+                    continue;
+                }
+                if let Err(e) =
+                    tracking::update_job_status(job_id, JobStatus::Stopped, None, &job_tracker_tx3)
+                        .await
+                {
+                    error!(
+                        job_id = job_id.as_str();
+                        "Failed to update job status to 'stopped' for lifetime exceeded job: {}",
+                        e
+                    );
+                    continue;
+                }
+            }
+            // Send remove command to the job executor for any stopped and expired jobs
+            for job_id in &stopped_and_expired_job_ids {
+                info!(
+                    job_id = job_id.as_str();
+                    "Sending 'remove' command for stopped job: {}",
+                    job_id
+                );
+                let command = JobExecutorCommand::Remove {
+                    job_id: job_id.clone(),
+                    force: settings.core.force_remove,
+                };
+                if let Err(e) = job_executor_tx3.send(command).await {
+                    error!(
+                        job_id = job_id.as_str();
+                        "Failed to send 'remove' command for stopped job: {}",
+                        e
+                    );
+                    continue;
+                }
+                if let Err(e) =
+                    tracking::update_job_status(job_id, JobStatus::Finished, None, &job_tracker_tx3)
                         .await
-                        .unwrap_or_default();
-                    let running_job_ids_length = running_job_ids.len();
-                    for job_id in running_job_ids {
-                        info!("Sending 'Stop' command for running job: {}", job_id);
-                        let command = JobExecutorCommand::Stop {
+                {
+                    error!(
+                        job_id = job_id.as_str();
+                        "Failed to update job status to 'finished' for stopped job: {}",
+                        e
+                    );
+                    continue;
+                }
+            }
+
+            // Once drained, shut down the same way as when a termination signal is received
+            if draining3.load(Ordering::SeqCst) && all_jobs_finished {
+                info!("All jobs finished, shutting down after drain");
+                running3.store(false, Ordering::SeqCst);
+            }
+
+            if !running3.load(Ordering::SeqCst) {
+                // Stop any running jobs (skipping those already stopped above)
+                let mut running_job_ids: Vec<String> = running_job_ids
+                    .into_iter()
+                    .filter(|job_id| {
+                        !timed_out_job_ids.contains(job_id)
+                            && !lifetime_exceeded_job_ids.contains(job_id)
+                    })
+                    .collect();
+                // Running job ids are ordered oldest first
+                if settings.core.shutdown_stop_order == StopOrder::NewestFirst {
+                    running_job_ids.reverse();
+                }
+                let running_job_ids_length = running_job_ids.len();
+                // Jobs are given the grace period to exit, but are killed if still running once
+                // the shutdown timeout has elapsed
+                let deadline = *shutdown_deadline.get_or_insert_with(|| {
+                    Instant::now() + Duration::from_millis(settings.core.shutdown_timeout)
+                });
+                let mut terminating = Vec::with_capacity(running_job_ids_length);
+                for job_id in running_job_ids {
+                    info!(
+                        job_id = job_id.as_str();
+                        "Sending 'Terminate' command for running job: {}",
+                        job_id
+                    );
+                    let (resp_tx, resp_rx) = oneshot::channel();
+                    let command = JobExecutorCommand::Terminate {
+                        job_id: job_id.clone(),
+                        deadline,
+                        resp: resp_tx,
+                    };
+                    // On failure the command is dropped, so the job is marked stopped straight away
+                    if let Err(e) = job_executor_tx3.send(command).await {
+                        error!(
+                            job_id = job_id.as_str();
+                            "Failed to send 'terminate' command for running job: {}",
+                            e
+                        );
+                    }
+                    terminating.push((job_id, resp_rx));
+                }
+                // Wait for jobs to exit before they are marked as stopped, so they aren't removed
+                // while still within their grace period
+                for (job_id, resp_rx) in terminating {
+                    let _ = resp_rx.await;
+                    if let Err(e) = tracking::update_job_status(
+                        &job_id,
+                        JobStatus::Stopped,
+                        None,
+                        &job_tracker_tx3,
+                    )
+                    .await
+                    {
+                        error!(
+                            job_id = job_id.as_str();
+                            "Failed to update job status to 'stopped' for running job: {}",
+                            e
+                        );
+                        continue;
+                    }
+                }
+                // Remove any stopped jobs (if allowed by settings)
+                let mut stopped_job_ids_length: usize = 0;
+                if settings.core.remove_stopped_containers_on_terminate {
+                    // Skip those already removed as stopped-and-expired above
+                    let stopped_job_ids: Vec<String> = stopped_job_ids
+                        .into_iter()
+                        .filter(|job_id| !stopped_and_expired_job_ids.contains(job_id))
+                        .collect();
+                    stopped_job_ids_length = stopped_job_ids.len();
+                    for job_id in stopped_job_ids {
+                        info!(
+                            job_id = job_id.as_str();
+                            "Sending 'remove' command for stopped job: {}",
+                            job_id
+                        );
+                        // Force removal so stuck containers don't prevent us from exiting
+                        let command = JobExecutorCommand::Remove {
                             job_id: job_id.clone(),
+                            force: true,
                         };
-                        job_executor_tx3.send(command).await.expect(
-                            "Failed to send 'stop' command to job executor for timed-out job",
-                        );
-                        tracking::update_job_status(
+                        // Still marked finished on failure, so shutdown isn't held up
+                        if let Err(e) = job_executor_tx3.send(command).await {
+                            error!(
+                                job_id = job_id.as_str();
+                                "Failed to send 'remove' command for stopped job: {}",
+                                e
+                            );
+                        }
+                        if let Err(e) = tracking::update_job_status(
                             &job_id,
-                            JobStatus::Stopped,
+                            JobStatus::Finished,
                             None,
                             &job_tracker_tx3,
                         )
                         .await
-                        .expect("Failed to update job status to 'stopped' for running job");
-                    }
-                    // Remove any stopped jobs (if allowed by settings)
-                    let mut stopped_job_ids_length: usize = 0;
-                    if settings.core.remove_stopped_containers_on_terminate {
-                        let stopped_job_ids = tracking::get_stopped_job_ids(&job_tracker_tx3)
-                            .await
-                            .unwrap_or_default();
-                        stopped_job_ids_length = stopped_job_ids.len();
-                        for job_id in stopped_job_ids {
-                            info!("Sending 'remove' command for stopped job: {}", job_id);
-                            let command = JobExecutorCommand::Remove {
-                                job_id: job_id.clone(),
-                            };
-                            job_executor_tx3.send(command).await.expect(
-                                "Failed to send 'remove' command to job executor for stopped job",
+                        {
+                            error!(
+                                job_id = job_id.as_str();
+                                "Failed to update job status to 'finished' for stopped job: {}",
+                                e
                             );
-                            tracking::update_job_status(
-                                &job_id,
-                                JobStatus::Finished,
-                                None,
-                                &job_tracker_tx3,
-                            )
-                            .await
-                            .expect("Failed to update job status to 'finished' for stopped job");
+                            continue;
                         }
                     }
+                }
 
-                    if running_job_ids_length == 0 && stopped_job_ids_length == 0 {
-                        info!("Stopping lifecycle task");
-                        break;
-                    } else {
-                        continue;
-                    }
+                if running_job_ids_length == 0 && stopped_job_ids_length == 0 {
+                    info!("Stopping lifecycle task");
+                    break;
+                } else {
+                    continue;
                 }
+            }
 
-                // Sleep for a while before checking again
-                tokio::time::sleep(Duration::from_millis(500)).await;
+            // Sleep for a while before checking again
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    });
+
+    // Fail any running jobs whose containers were killed for exceeding their memory limit, as they
+    // can no longer report a status themselves. Kept separate from the lifecycle task so inspecting
+    // containers doesn't hold up stopping and removing jobs.
+    let running5 = running.clone();
+    let job_tracker_tx12 = job_tracker_tx.clone();
+    let job_executor_tx8 = job_executor_tx.clone();
+    let dead_letters6 = dead_letters.clone();
+    let job_metrics6 = job_metrics.clone();
+    tokio::spawn(async move {
+        let reconcile_interval = Duration::from_millis(settings.core.reconcile_interval);
+        loop {
+            tokio::time::sleep(reconcile_interval).await;
+            if !running5.load(Ordering::SeqCst) {
+                break;
             }
-        });
+            let running_job_ids = match tracking::get_job_id_buckets(&job_tracker_tx12).await {
+                std::result::Result::Ok(job_id_buckets) => job_id_buckets.running,
+                Err(e) => {
+                    error!("Failed to get running jobs to reconcile: {}", e);
+                    continue;
+                }
+            };
+            if running_job_ids.is_empty() {
+                continue;
+            }
+            let (resp_tx, resp_rx) = oneshot::channel();
+            let command = JobExecutorCommand::Exits {
+                job_ids: running_job_ids,
+                resp: resp_tx,
+            };
+            if job_executor_tx8
+                .send_timeout(command, EXECUTOR_TIMEOUT)
+                .await
+                .is_err()
+            {
+                warn!("Failed to send 'exits' command to job executor");
+                continue;
+            }
+            let exits = match tokio::time::timeout(EXECUTOR_TIMEOUT, resp_rx).await {
+                std::result::Result::Ok(std::result::Result::Ok(exits)) => exits,
+                _ => {
+                    warn!("Job executor did not report which running jobs have exited");
+                    continue;
+                }
+            };
+            for (job_id, exit) in exits {
+                if !exit.oom_killed {
+                    continue;
+                }
+                // The job may have been stopped while it's container was being inspected
+                let still_running = matches!(
+                    tracking::get_job(&job_id, &job_tracker_tx12).await,
+                    std::result::Result::Ok(Some(tracked_job))
+                        if *tracked_job.lock().unwrap().status() == JobStatus::Running
+                );
+                if !still_running {
+                    continue;
+                }
+                warn!(job_id = job_id.as_str(); "Job's container was OOM killed: {}", job_id);
+                if let Err(e) =
+                    tracking::set_job_result(&job_id, exit.exit_code, None, &job_tracker_tx12).await
+                {
+                    error!(job_id = job_id.as_str(); "Error setting job result: {}", e);
+                }
+                if let Err(e) = fail_job(
+                    &job_id,
+                    FailureReason::OomKilled,
+                    "Container was killed after exceeding it's memory limit",
+                    &job_tracker_tx12,
+                    &dead_letters6,
+                    &job_metrics6,
+                )
+                .await
+                {
+                    error!(
+                        job_id = job_id.as_str();
+                        "Failed to update job status to 'failed' for OOM killed job: {}",
+                        e
+                    );
+                    continue;
+                }
+                let command = JobExecutorCommand::Remove {
+                    job_id: job_id.clone(),
+                    force: settings.core.force_remove,
+                };
+                if job_executor_tx8.send(command).await.is_err() {
+                    error!(
+                        job_id = job_id.as_str();
+                        "Failed to send 'remove' command to job executor for OOM killed job"
+                    );
+                }
+            }
+        }
+    });
 
     let job_tracker_tx4 = job_tracker_tx.clone();
     let job_tracker_tx5 = job_tracker_tx.clone();
+    let job_executor_tx4 = job_executor_tx.clone();
+    let docker_ready4 = docker_ready.clone();
+    let draining4 = draining.clone();
+    let draining5 = draining.clone();
+    let draining6 = draining.clone();
+    let docker_ready5 = docker_ready.clone();
+    let docker_degraded4 = docker_degraded.clone();
+    let docker_degraded5 = docker_degraded.clone();
+    let job_tracker_tx7 = job_tracker_tx.clone();
+    let job_tracker_tx8 = job_tracker_tx.clone();
+    let job_executor_tx5 = job_executor_tx.clone();
+    let paused3 = paused.clone();
+    let paused4 = paused.clone();
+    let paused5 = paused.clone();
+    let job_tracker_tx6 = job_tracker_tx.clone();
+    let events2 = events.clone();
+    let result_sinks2 = result_sinks.clone();
+    let job_tracker_tx10 = job_tracker_tx.clone();
+    let job_executor_tx7 = job_executor_tx.clone();
+    let job_tracker_tx11 = job_tracker_tx.clone();
+    let result_sinks3 = result_sinks.clone();
+    let poll_metrics3 = poll_metrics.clone();
+    let dead_letters4 = dead_letters.clone();
+    let dead_letters5 = dead_letters.clone();
+    let job_metrics4 = job_metrics.clone();
+    let job_metrics5 = job_metrics.clone();
+
     let app = Router::new()
         .route(
-            "/job/:job_id",
+            "/events",
+            get(|| async move {
+                // Each subscriber only receives events emitted after it subscribes
+                let rx = events2.subscribe();
+                let stream = futures::stream::unfold(rx, |mut rx| async move {
+                    loop {
+                        match rx.recv().await {
+                            std::result::Result::Ok(event) => {
+                                return Some((Event::default().json_data(&event), rx))
+                            }
+                            Err(RecvError::Lagged(count)) => {
+                                warn!("Events stream lagged, skipped {} events", count);
+                            }
+                            Err(RecvError::Closed) => return None,
+                        }
+                    }
+                });
+                Sse::new(stream).keep_alive(KeepAlive::default())
+            }),
+        )
+        .route(
+            "/job/:job_id/stats",
             get(|Path(job_id): Path<String>| async move {
-                let job_opt = tracking::get_job(&job_id, &job_tracker_tx4).await;
+                let job_opt = match tracking::get_job(&job_id, &job_tracker_tx8).await {
+                    std::result::Result::Ok(job_opt) => job_opt,
+                    Err(e) => return tracker_unavailable(e),
+                };
                 if job_opt.is_none() {
                     return (StatusCode::NOT_FOUND, Json(json!({ "error": "not found" })));
                 }
-
-                let tracked_job = {
-                    let tracked_job = job_opt.unwrap();
-                    let tracked_job = tracked_job.lock().unwrap();
-                    tracked_job.clone() // FIXME: I don't love the clone here :(
+                let (resp_tx, resp_rx) = oneshot::channel();
+                let command = JobExecutorCommand::Stats {
+                    job_id: job_id.clone(),
+                    resp: resp_tx,
                 };
-                let Job::Docker(docker_job) = tracked_job.inner();
-
-                match *tracked_job.status() {
-                    JobStatus::Completed => {
-                         return (
-                            StatusCode::FORBIDDEN,
-                            Json(json!({ "error": "refusing to return job as it's status is 'completed'" })),
-                        );
-                    },
-                    JobStatus::Pending => {
-                        if let Err(e) = tracking::update_job_status(
-                            &docker_job.id,
-                            JobStatus::Running,
-                            Some(0.0),
-                            &job_tracker_tx4,
+                if job_executor_tx5
+                    .send_timeout(command, EXECUTOR_TIMEOUT)
+                    .await
+                    .is_err()
+                {
+                    return executor_unavailable(anyhow!(
+                        "Failed to send stats command to job executor"
+                    ));
+                }
+                let result = match tokio::time::timeout(EXECUTOR_TIMEOUT, resp_rx).await {
+                    std::result::Result::Ok(std::result::Result::Ok(result)) => result,
+                    std::result::Result::Ok(Err(_)) => {
+                        return executor_unavailable(anyhow!(
+                            "Job executor dropped the stats command"
+                        ))
+                    }
+                    Err(_) => {
+                        return executor_unavailable(anyhow!(
+                            "Timed out waiting for the job executor to respond"
+                        ))
+                    }
+                };
+                match result {
+                    std::result::Result::Ok(Some(stats)) => (StatusCode::OK, Json(json!(stats))),
+                    std::result::Result::Ok(None) => (
+                        StatusCode::GONE,
+                        Json(json!({ "error": "container no longer exists" })),
+                    ),
+                    Err(e) => {
+                        error!(job_id = job_id.as_str(); "Failed to get job stats: {}", e);
+                        (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            Json(json!({ "error": "failed to get job stats" })),
                         )
-                        .await {
-                            error!("Failed to update job status: {}", e);
-                            return (
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                                Json(json!({ "error": "failed to update job status" })),
-                            );
-                        };
                     }
-                    _ => {}
                 }
-
-                (StatusCode::OK, Json(json!({ "id": docker_job.id, "body": docker_job.body })))
             }),
         )
         .route(
-            "/job/:job_id",
-            put(
-                |Path(job_id): Path<String>, headers: HeaderMap, body: Bytes| async move {
-                    info!("Received PUT request for job ID: {}", job_id);
-                    debug!("Headers: {:?}", headers);
-                    let status: JobStatus = match headers.get("x-foreman-job-status") {
-                        Some(hv) => match hv.to_str() {
-                            std::result::Result::Ok(s) => match s.parse() {
-                                std::result::Result::Ok(js) => js,
-                                Err(e) => {
-                                    let error_msg =
-                                        format!("Invalid header x-foreman-job-status: {}", e);
-                                    error!("{}", error_msg);
-                                    return (StatusCode::BAD_REQUEST, error_msg);
-                                }
-                            },
-                            Err(e) => {
-                                let error_msg =
-                                    format!("Failed to parse x-foreman-job-status header: {}", e);
-                                error!("{}", error_msg);
-                                return (StatusCode::BAD_REQUEST, error_msg);
-                            }
-                        },
-                        None => {
-                            let error_msg = "Missing x-foreman-job-status header";
-                            error!("{}", error_msg);
-                            return (StatusCode::BAD_REQUEST, error_msg.to_string());
+            "/job/:job_id/force-remove",
+            post(|Path(job_id): Path<String>| async move {
+                let tracked_job = match tracking::get_job(&job_id, &job_tracker_tx10).await {
+                    std::result::Result::Ok(Some(tracked_job)) => tracked_job,
+                    std::result::Result::Ok(None) => {
+                        return (StatusCode::NOT_FOUND, Json(json!({ "error": "not found" })))
+                    }
+                    Err(e) => return tracker_unavailable(e),
+                };
+                let status = tracked_job.lock().unwrap().status().clone();
+                warn!(job_id = job_id.as_str(); "Force removing job's container");
+                let command = JobExecutorCommand::Remove {
+                    job_id: job_id.clone(),
+                    force: true,
+                };
+                if job_executor_tx7
+                    .send_timeout(command, EXECUTOR_TIMEOUT)
+                    .await
+                    .is_err()
+                {
+                    return executor_unavailable(anyhow!(
+                        "Failed to send remove command to job executor"
+                    ));
+                }
+                // A job whose work was done is finished, otherwise it failed
+                let status = match status {
+                    JobStatus::Pending | JobStatus::Running => JobStatus::Failed,
+                    JobStatus::Completed | JobStatus::Stopped => JobStatus::Finished,
+                    status => status,
+                };
+                let result = if status == JobStatus::Failed {
+                    fail_job(
+                        &job_id,
+                        FailureReason::ForceRemoved,
+                        "Force removed",
+                        &job_tracker_tx10,
+                        &dead_letters4,
+                        &job_metrics4,
+                    )
+                    .await
+                } else {
+                    tracking::update_job_status(&job_id, status.clone(), None, &job_tracker_tx10)
+                        .await
+                };
+                if let Err(e) = result {
+                    error!(job_id = job_id.as_str(); "Failed to update job status: {}", e);
+                }
+                (
+                    StatusCode::ACCEPTED,
+                    Json(json!({ "id": job_id, "status": status })),
+                )
+            }),
+        )
+        .route(
+            "/job/:job_id/ws",
+            get(
+                |Path(job_id): Path<String>, ws: WebSocketUpgrade| async move {
+                    let job_opt = match tracking::get_job(&job_id, &job_tracker_tx6).await {
+                        std::result::Result::Ok(job_opt) => job_opt,
+                        Err(e) => return tracker_unavailable(e).into_response(),
+                    };
+                    let Some(tracked_job) = job_opt else {
+                        return (StatusCode::NOT_FOUND, Json(json!({ "error": "not found" })))
+                            .into_response();
+                    };
+                    // Subscribe before reading the current status so no updates are missed
+                    let rx = events.subscribe();
+                    let current = {
+                        let tracked_job = tracked_job.lock().unwrap();
+                        JobStatusUpdate {
+                            job_id: job_id.clone(),
+                            status: tracked_job.status().clone(),
+                            progress: tracked_job.progress(),
+                            failure_reason: tracked_job.failure_reason(),
                         }
                     };
+                    ws.on_upgrade(move |socket| job_status_socket(socket, current, rx))
+                },
+            ),
+        )
+        .route(
+            "/ready",
+            get(|| async move {
+                if docker_ready4.load(Ordering::SeqCst)
+                    && !docker_degraded4.load(Ordering::SeqCst)
+                    && !draining4.load(Ordering::SeqCst)
+                {
+                    (StatusCode::OK, Json(json!({ "ready": true })))
+                } else {
+                    (
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        Json(json!({ "ready": false })),
+                    )
+                }
+            }),
+        )
+        .route(
+            "/status",
+            get(move || async move {
+                let job_id_buckets = match tracking::get_job_id_buckets(&job_tracker_tx7).await {
+                    std::result::Result::Ok(job_id_buckets) => job_id_buckets,
+                    Err(e) => return tracker_unavailable(e),
+                };
+                (
+                    StatusCode::OK,
+                    Json(json!({
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "uptime_secs": started_at.elapsed().as_secs(),
+                        "docker_connected": docker_ready5.load(Ordering::SeqCst),
+                        "docker_degraded": docker_degraded5.load(Ordering::SeqCst),
+                        "paused": paused3.load(Ordering::SeqCst),
+                        "draining": draining6.load(Ordering::SeqCst),
+                        "jobs": {
+                            "pending": job_id_buckets.pending.len(),
+                            "running": job_id_buckets.running.len(),
+                            "max_concurrent": settings.core.max_concurrent_jobs,
+                        },
+                    })),
+                )
+            }),
+        )
+        .route(
+            "/dead-letters",
+            get(|| async move { Json(dead_letters5.list()) }),
+        )
+        .route(
+            "/metrics",
+            get(|| async move {
+                let mut body = poll_metrics3.lock().unwrap().render();
+                body.push_str(&job_metrics5.lock().unwrap().render());
+                ([(CONTENT_TYPE, metrics::CONTENT_TYPE)], body)
+            }),
+        )
+        .route(
+            "/pause",
+            post(|| async move {
+                info!("Pausing polling for new jobs");
+                paused4.store(true, Ordering::SeqCst);
+                Json(json!({ "paused": true }))
+            }),
+        )
+        .route(
+            "/resume",
+            post(|| async move {
+                info!("Resuming polling for new jobs");
+                paused5.store(false, Ordering::SeqCst);
+                Json(json!({ "paused": false }))
+            }),
+        )
+        .route(
+            "/drain",
+            post(|| async move {
+                info!("Drain requested, no longer accepting new jobs");
+                draining5.store(true, Ordering::SeqCst);
+                (StatusCode::ACCEPTED, Json(json!({ "draining": true })))
+            }),
+        )
+        .route(
+            "/jobs/validate",
+            post(|Json(job): Json<Job>| async move {
+                let job_id = job.id().to_owned();
+                let (resp_tx, resp_rx) = oneshot::channel();
+                let command = JobExecutorCommand::Validate {
+                    job: Box::new(job),
+                    resp: resp_tx,
+                };
+                if job_executor_tx4
+                    .send_timeout(command, EXECUTOR_TIMEOUT)
+                    .await
+                    .is_err()
+                {
+                    return executor_unavailable(anyhow!(
+                        "Failed to send validate command to job executor"
+                    ));
+                }
 
-                    let progress: f64 = headers
-                        .get("x-foreman-job-progress")
-                        .and_then(|hv| hv.to_str().ok())
-                        .and_then(|s| s.parse().ok())
-                        .unwrap_or(0.0);
-
-                    // Get the job object from the JobTracker
-                    let job_opt = tracking::get_job(&job_id, &job_tracker_tx5).await;
-                    if job_opt.is_none() {
-                        return (StatusCode::NOT_FOUND, "Job not found".to_string());
+                let result = match tokio::time::timeout(EXECUTOR_TIMEOUT, resp_rx).await {
+                    std::result::Result::Ok(std::result::Result::Ok(result)) => result,
+                    std::result::Result::Ok(Err(_)) => {
+                        return executor_unavailable(anyhow!(
+                            "Job executor dropped the validate command"
+                        ))
                     }
-                    let callback_url = {
-                        let tracked_job = job_opt.unwrap();
-                        let tracked_job = tracked_job.lock().unwrap();
-                        let Job::Docker(docker_job) = &tracked_job.inner();
-                        docker_job.callback_url.clone()
-                    };
+                    Err(_) => {
+                        return executor_unavailable(anyhow!(
+                            "Timed out waiting for the job executor to respond"
+                        ))
+                    }
+                };
+                match result {
+                    std::result::Result::Ok(()) => {
+                        (StatusCode::OK, Json(json!({ "id": job_id, "valid": true })))
+                    }
+                    Err(e) => {
+                        info!(job_id = job_id.as_str(); "Job failed validation: {}", e);
+                        (
+                            StatusCode::UNPROCESSABLE_ENTITY,
+                            Json(json!({
+                                "id": job_id,
+                                "valid": false,
+                                "error": e.to_string(),
+                            })),
+                        )
+                    }
+                }
+            }),
+        )
+        .route(
+            "/job/:job_id",
+            get(|Path(job_id): Path<String>| async move {
+                let job_opt = match tracking::get_job(&job_id, &job_tracker_tx4).await {
+                    std::result::Result::Ok(job_opt) => job_opt,
+                    Err(e) => return tracker_unavailable(e),
+                };
+                if job_opt.is_none() {
+                    return (StatusCode::NOT_FOUND, Json(json!({ "error": "not found" })));
+                }
 
-                    // Send a PUT request to the callback URL
-                    info!("Sending PUT request to callback URL {}", callback_url);
-                    let http_client = reqwest::Client::new();
-                    let mut headers = headers.clone();
-                    headers.insert("user-agent", HeaderValue::from_str(&USER_AGENT).unwrap());
-                    let resp = http_client
-                        .put(callback_url)
-                        .headers(headers)
-                        .body(Into::<reqwest::Body>::into(body))
-                        .send()
-                        .await;
-                    if let std::result::Result::Ok(resp) = resp {
-                        let status_code = resp.status();
-                        info!("- Status code {}", status_code);
-                    } else {
-                        let error_msg = format!("Failed to send PUT request: {:?}", resp);
-                        error!("{}", error_msg);
-                        return (StatusCode::BAD_REQUEST, error_msg);
-                    }
-
-                    // Update the job status in the JobTracker.
-                    if let Err(e) = tracking::update_job_status(&job_id, status, Some(progress), &job_tracker_tx5).await {
-                        error!("Error updating job status: {}", e);
+                let tracked_job = {
+                    let tracked_job = job_opt.unwrap();
+                    let tracked_job = tracked_job.lock().unwrap();
+                    tracked_job.clone() // FIXME: I don't love the clone here :(
+                };
+                let Job::Docker(docker_job) = tracked_job.inner();
+
+                // Once a job's work is done it can no longer be fetched, see `JobStatus`
+                if !tracked_job.status().is_fetchable() {
+                    let status = format!("{:?}", tracked_job.status()).to_lowercase();
+                    let error_msg =
+                        format!("refusing to return job as it's status is '{}'", status);
+                    return (StatusCode::FORBIDDEN, Json(json!({ "error": error_msg })));
+                }
+                // Fetching a pending job marks it as running
+                if *tracked_job.status() == JobStatus::Pending {
+                    if let Err(e) = tracking::update_job_status(
+                        &docker_job.id,
+                        JobStatus::Running,
+                        Some(0.0),
+                        &job_tracker_tx4,
+                    )
+                    .await
+                    {
+                        error!(
+                            job_id = docker_job.id.as_str();
+                            "Failed to update job status: {}",
+                            e
+                        );
                         return (
                             StatusCode::INTERNAL_SERVER_ERROR,
-                            "Failed to update job status".to_string(),
+                            Json(json!({ "error": "failed to update job status" })),
                         );
                     };
+                }
 
-                    (StatusCode::OK, "OK".to_string())
+                (
+                    StatusCode::OK,
+                    Json(json!({
+                        "id": docker_job.id,
+                        "body": docker_job.body,
+                        "exitCode": tracked_job.exit_code(),
+                        "result": tracked_job.result(),
+                    })),
+                )
+            }),
+        )
+        .route(
+            "/job/:job_id/updates",
+            put(
+                |Path(job_id): Path<String>,
+                 updates: Result<Json<Vec<JobUpdate>>, JsonRejection>| async move {
+                    receive_job_updates(&result_sinks3, &job_tracker_tx11, &job_id, updates).await
                 },
             ),
-        );
+        )
+        .route(
+            "/job/:job_id",
+            put(
+                move |Path(job_id): Path<String>,
+                      headers: HeaderMap,
+                      body: Result<Bytes, BytesRejection>| async move {
+                    receive_job_update(&result_sinks2, &job_tracker_tx5, &job_id, headers, body)
+                        .await
+                },
+            )
+            // Reject oversized callback bodies with 413 rather than buffering them in memory
+            .layer(DefaultBodyLimit::max(settings.core.max_callback_body)),
+        )
+        // Log every request, after routing so the job id path parameter is available
+        .layer(middleware::from_fn(access_log::log_request));
 
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", settings.core.port)).await?;
-    let server = axum::serve(listener, app);
+    // Serve over HTTPS when a certificate is configured
+    let tls_config = match (&settings.core.tls_cert, &settings.core.tls_key) {
+        (Some(cert), Some(key)) => Some(tls::load_server_config(cert, key)?),
+        _ => None,
+    };
+    let listener = tokio::net::TcpListener::bind(SocketAddr::new(
+        settings.core.bind_address,
+        settings.core.port,
+    ))
+    .await?;
+    let server_task = tokio::spawn(async move {
+        match tls_config {
+            Some(config) => tls::serve(listener, app, config).await,
+            None => Ok(axum::serve(listener, app).await?),
+        }
+    });
 
     // Set up a Ctrl-C handler to gracefully shut down
     let running4 = running.clone();
     ctrlc::set_handler(move || {
         println!("Termination signal received, shutting down...");
         running4.store(false, Ordering::SeqCst);
-        std::thread::sleep(Duration::from_secs(3));
+        // Give running jobs time to stop before exiting regardless
+        std::thread::sleep(
+            Duration::from_millis(settings.core.shutdown_timeout) + Duration::from_secs(3),
+        );
+        telemetry::shutdown();
         std::process::exit(0);
     })
     .expect("Error setting Ctrl-C handler");
 
-    let _ = join!(
-        control_server_poller_task,
-        job_manager_task,
-        job_tracking_task,
-        job_lifecycle_task,
-        server
-    );
+    // The poller and lifecycle tasks complete once we stop running, either due to a termination
+    // signal or after draining
+    let _ = join!(control_server_poller_task, job_lifecycle_task);
+
+    // Wait for the executor to process any outstanding commands e.g., removing stopped containers
+    if docker_ready.load(Ordering::SeqCst) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        if job_executor_tx
+            .send(JobExecutorCommand::Flush { resp: resp_tx })
+            .await
+            .is_ok()
+        {
+            let _ = resp_rx.await;
+        }
+    }
+    server_task.abort();
+    telemetry::shutdown();
 
     Ok(())
 }
@@ -0,0 +1,188 @@
+use std::{
+    fmt::Write,
+    time::{Duration, SystemTime},
+};
+
+use crate::tracking::FailureReason;
+
+/// The content type of metrics rendered in the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Upper bounds, in seconds, of the poll duration histogram buckets.
+const POLL_DURATION_BUCKETS: [f64; 10] = [0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// The outcome of polling the control server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PollOutcome {
+    /// The control server responded with jobs.
+    Ok,
+    /// The control server responded with no jobs.
+    Empty,
+    /// The control server could not be reached or it's response was invalid.
+    Error,
+}
+
+impl PollOutcome {
+    const ALL: [PollOutcome; 3] = [PollOutcome::Ok, PollOutcome::Empty, PollOutcome::Error];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            PollOutcome::Ok => "ok",
+            PollOutcome::Empty => "empty",
+            PollOutcome::Error => "error",
+        }
+    }
+}
+
+/// Metrics describing foreman's polling of the control server.
+#[derive(Debug, Default)]
+pub struct PollMetrics {
+    /// Cumulative count of polls per histogram bucket, excluding the implicit `+Inf` bucket.
+    bucket_counts: [u64; POLL_DURATION_BUCKETS.len()],
+    duration_sum: f64,
+    outcome_counts: [u64; PollOutcome::ALL.len()],
+    last_success: Option<SystemTime>,
+}
+
+impl PollMetrics {
+    /// Record a single poll request.
+    pub fn record(&mut self, duration: Duration, outcome: PollOutcome) {
+        let secs = duration.as_secs_f64();
+        for (bound, count) in POLL_DURATION_BUCKETS.iter().zip(&mut self.bucket_counts) {
+            if secs <= *bound {
+                *count += 1;
+            }
+        }
+        self.duration_sum += secs;
+        self.outcome_counts[outcome as usize] += 1;
+        if outcome != PollOutcome::Error {
+            self.last_success = Some(SystemTime::now());
+        }
+    }
+
+    fn count(&self) -> u64 {
+        self.outcome_counts.iter().sum()
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str(
+            "# HELP foreman_poll_duration_seconds \
+             Round-trip time of polls to the control server.\n",
+        );
+        out.push_str("# TYPE foreman_poll_duration_seconds histogram\n");
+        for (bound, count) in POLL_DURATION_BUCKETS.iter().zip(&self.bucket_counts) {
+            let _ = writeln!(
+                out,
+                "foreman_poll_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "foreman_poll_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            self.count()
+        );
+        let _ = writeln!(
+            out,
+            "foreman_poll_duration_seconds_sum {}",
+            self.duration_sum
+        );
+        let _ = writeln!(out, "foreman_poll_duration_seconds_count {}", self.count());
+
+        out.push_str("# HELP foreman_polls_total Polls to the control server by outcome.\n");
+        out.push_str("# TYPE foreman_polls_total counter\n");
+        for outcome in PollOutcome::ALL {
+            let _ = writeln!(
+                out,
+                "foreman_polls_total{{outcome=\"{}\"}} {}",
+                outcome.as_str(),
+                self.outcome_counts[outcome as usize]
+            );
+        }
+
+        // Only reported once a poll has succeeded, so absence can be alerted on
+        if let Some(last_success) = self.last_success {
+            let timestamp = last_success
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64();
+            out.push_str(
+                "# HELP foreman_poll_last_success_timestamp \
+                 Unix time of the last successful poll.\n",
+            );
+            out.push_str("# TYPE foreman_poll_last_success_timestamp gauge\n");
+            let _ = writeln!(out, "foreman_poll_last_success_timestamp {}", timestamp);
+        }
+        out
+    }
+}
+
+/// Metrics describing the outcome of jobs.
+#[derive(Debug, Default)]
+pub struct JobMetrics {
+    failure_counts: [u64; FailureReason::ALL.len()],
+}
+
+impl JobMetrics {
+    /// Record a job failing.
+    pub fn record_failure(&mut self, reason: FailureReason) {
+        self.failure_counts[reason as usize] += 1;
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP foreman_job_failures_total Jobs which failed by reason.\n");
+        out.push_str("# TYPE foreman_job_failures_total counter\n");
+        for reason in FailureReason::ALL {
+            let _ = writeln!(
+                out,
+                "foreman_job_failures_total{{reason=\"{}\"}} {}",
+                reason.as_str(),
+                self.failure_counts[reason as usize]
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_job_metrics() {
+        let mut metrics = JobMetrics::default();
+        metrics.record_failure(FailureReason::OomKilled);
+        metrics.record_failure(FailureReason::OomKilled);
+        metrics.record_failure(FailureReason::PrepareFailed);
+        let rendered = metrics.render();
+        assert!(rendered.contains("foreman_job_failures_total{reason=\"oom_killed\"} 2\n"));
+        assert!(rendered.contains("foreman_job_failures_total{reason=\"prepare_failed\"} 1\n"));
+        assert!(rendered.contains("foreman_job_failures_total{reason=\"force_removed\"} 0\n"));
+    }
+
+    #[test]
+    fn test_render_poll_metrics() {
+        let mut metrics = PollMetrics::default();
+        assert!(!metrics
+            .render()
+            .contains("foreman_poll_last_success_timestamp"));
+
+        metrics.record(Duration::from_millis(200), PollOutcome::Ok);
+        metrics.record(Duration::from_millis(40), PollOutcome::Empty);
+        metrics.record(Duration::from_secs(60), PollOutcome::Error);
+        let rendered = metrics.render();
+        assert!(rendered.contains("foreman_poll_duration_seconds_bucket{le=\"0.05\"} 1\n"));
+        assert!(rendered.contains("foreman_poll_duration_seconds_bucket{le=\"0.25\"} 2\n"));
+        assert!(rendered.contains("foreman_poll_duration_seconds_bucket{le=\"30\"} 2\n"));
+        assert!(rendered.contains("foreman_poll_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(rendered.contains("foreman_poll_duration_seconds_count 3\n"));
+        assert!(rendered.contains("foreman_polls_total{outcome=\"ok\"} 1\n"));
+        assert!(rendered.contains("foreman_polls_total{outcome=\"empty\"} 1\n"));
+        assert!(rendered.contains("foreman_polls_total{outcome=\"error\"} 1\n"));
+        assert!(rendered.contains("foreman_poll_last_success_timestamp "));
+    }
+}
@@ -1,14 +1,25 @@
 use std::collections::HashMap;
-use std::sync::LazyLock;
-use std::{env, path::Path};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{LazyLock, OnceLock};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
 
+use anyhow::bail;
 use config::{Config, ConfigError, Environment, File, FileFormat, FileSourceFile};
-use serde::Deserialize;
-use urlencoding::encode;
+use reqwest::header::HeaderValue;
+use serde::{Deserialize, Serialize};
+use urlencoding::{decode, encode};
 
-use crate::env::EnvVars;
+use crate::{
+    env::EnvVars,
+    job::{CallbackMethod, Ulimits},
+    logging::LogFormat,
+};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
 pub struct LabelMap(HashMap<String, String>);
 
 impl LabelMap {
@@ -30,19 +41,121 @@ impl From<&LabelMap> for String {
     }
 }
 
+impl FromStr for LabelMap {
+    type Err = anyhow::Error;
+
+    /// Parse a string in the format "key=value,key=value", as produced by
+    /// `From<&LabelMap> for String`. Both keys and values are URL-decoded.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut label_map = LabelMap::new();
+        for pair in s.split(',').filter(|pair| !pair.is_empty()) {
+            let Some((k, v)) = pair.split_once('=') else {
+                bail!("Invalid label '{}', expected 'key=value'", pair);
+            };
+            label_map
+                .0
+                .insert(decode(k)?.into_owned(), decode(v)?.into_owned());
+        }
+        Ok(label_map)
+    }
+}
+
 impl Default for LabelMap {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Where foreman fetches jobs from.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum JobSourceKind {
+    /// Poll the control server at `core.url` over HTTP.
+    #[default]
+    Http,
+}
+
+/// The order running jobs are stopped in on shutdown, by when each job was received.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StopOrder {
+    #[default]
+    OldestFirst,
+    NewestFirst,
+}
+
+/// How progress reported by a job outside the range 0.0 to 1.0 is handled.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutOfRangeProgress {
+    /// Reject the update with `400`.
+    #[default]
+    Reject,
+    /// Clamp the progress to the nearest end of the range.
+    Clamp,
+}
+
+/// Where updates reported by jobs are published to.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultSinkKind {
+    /// PUT to the job's `callbackUrl`.
+    Callback,
+    /// Append to `core.result_file` as newline delimited JSON.
+    File,
+}
+
+/// HTTP method used to poll the control server for jobs.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum PollMethod {
+    #[default]
+    Get,
+    /// Sends a JSON body containing foreman's labels.
+    Post,
+}
+
+/// Query parameters which may be appended to requests polling the control server for jobs.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum PollQueryParam {
+    /// Number of additional jobs foreman can run concurrently.
+    Slots,
+    /// Labels formatted as for the `x-foreman-labels` header.
+    Labels,
+    /// Foreman's version.
+    Version,
+}
+
+impl PollQueryParam {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PollQueryParam::Slots => "slots",
+            PollQueryParam::Labels => "labels",
+            PollQueryParam::Version => "version",
+        }
+    }
+
+    pub fn value(&self, labels: Option<&LabelMap>, free_slots: u64) -> String {
+        match self {
+            PollQueryParam::Slots => free_slots.to_string(),
+            PollQueryParam::Labels => labels.map(String::from).unwrap_or_default(),
+            PollQueryParam::Version => env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
 /// Resolves the configuration file by checking the following locations in order:
 ///
-/// 1. The path specified by the `FOREMAN_CONFIG` environment variable
-/// 2. ./foreman.toml
-/// 3. /etc/foreman/foreman.toml
-/// 4. $HOME/.foreman/foreman.toml
+/// 1. The path passed via the `--config` command line argument
+/// 2. The path specified by the `FOREMAN_CONFIG` environment variable
+/// 3. ./foreman.toml
+/// 4. /etc/foreman/foreman.toml
+/// 5. $HOME/.foreman/foreman.toml
 fn get_config_file() -> Option<File<FileSourceFile, FileFormat>> {
+    if let Some(path) = CONFIG_PATH.get() {
+        return Some(File::from(path.as_path()));
+    }
     // If FOREMAN_CONFIG environment variable is set and it points to a valid file, use that.
     // Otherwise panic!
     if let Ok(val) = env::var("FOREMAN_CONFIG") {
@@ -74,36 +187,135 @@ fn get_config_file() -> Option<File<FileSourceFile, FileFormat>> {
     None
 }
 
-#[derive(Debug, Deserialize)]
+/// Serialize a secret as a placeholder so it's never output e.g., by `foreman config`.
+fn redact<S: serde::Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    if value.is_empty() {
+        serializer.serialize_str("")
+    } else {
+        serializer.serialize_str("<redacted>")
+    }
+}
+
+/// Deserialize either a single string or a list of strings.
+fn string_or_list<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrList {
+        String(String),
+        List(Vec<String>),
+    }
+    Ok(match StringOrList::deserialize(deserializer)? {
+        StringOrList::String(s) => vec![s],
+        StringOrList::List(list) => list,
+    })
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 #[allow(unused)]
 pub struct Core {
-    pub url: String,
+    #[serde(deserialize_with = "string_or_list")]
+    pub url: Vec<String>,
     pub hostname: String,
     pub port: u16,
+    pub bind_address: IpAddr,
     pub network_name: String,
+    pub network_external: bool,
+    pub network_driver: String,
+    pub network_enable_ipv6: bool,
+    pub network_subnet: Option<String>,
+    pub network_gateway: Option<IpAddr>,
+    #[serde(default, serialize_with = "redact")]
     pub token: String,
+    pub token_file: Option<PathBuf>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub token_refresh_interval: u64,
     pub poll_frequency: u16,
     pub poll_timeout: u16,
+    pub long_poll: bool,
+    pub long_poll_timeout: u64,
+    pub source: JobSourceKind,
+    pub poll_method: PollMethod,
+    pub poll_accept: String,
+    #[serde(default)]
+    pub poll_query_params: Vec<PollQueryParam>,
+    pub ack_url: Option<String>,
+    pub callback_timeout: u64,
+    pub result_sinks: Vec<ResultSinkKind>,
+    pub result_file: Option<PathBuf>,
+    pub dead_letter_capacity: usize,
+    pub dead_letter_file: Option<PathBuf>,
+    pub callback_method: CallbackMethod,
+    pub max_callback_body: usize,
+    pub max_updates_per_second: Option<u32>,
+    pub out_of_range_progress: OutOfRangeProgress,
+    pub finished_job_retention: u64,
     pub extra_hosts: Option<Vec<String>>,
+    pub dns: Option<Vec<String>>,
+    pub dns_search: Option<Vec<String>>,
+    pub ulimits: Option<Ulimits>,
+    pub shm_size: Option<String>,
+    pub log_driver: Option<String>,
+    pub log_driver_opts: Option<HashMap<String, String>>,
+    pub read_only_rootfs: bool,
+    pub cap_add: Option<Vec<String>>,
+    pub cap_drop: Option<Vec<String>>,
+    pub no_new_privileges: bool,
+    pub default_user: Option<String>,
     pub labels: Option<LabelMap>,
     pub job_completion_timeout: u64,
     pub job_removal_timeout: u64,
+    pub job_start_timeout: u64,
+    pub pull_timeout: u64,
+    pub max_job_lifetime: Option<u64>,
+    pub reconcile_interval: u64,
     pub remove_stopped_containers_on_terminate: bool,
+    pub remove_on_complete_immediately: bool,
+    pub auto_remove: bool,
+    pub force_remove: bool,
+    pub apply_managed_label: bool,
+    pub stop_timeout: u64,
+    pub shutdown_stop_order: StopOrder,
+    pub shutdown_grace_period: u64,
+    pub shutdown_timeout: u64,
     pub max_concurrent_jobs: u64,
+    pub max_jobs_per_poll: Option<usize>,
+    pub executor_queue_size: usize,
+    pub tracker_queue_size: usize,
     pub env: Option<EnvVars>,
+    pub log_format: LogFormat,
+    pub log_level: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[allow(unused)]
 pub struct Docker {
     pub url: Option<String>,
+    pub api_version: Option<String>,
+    pub tls_ca: Option<String>,
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    pub circuit_breaker_threshold: u32,
+    pub circuit_breaker_cooldown: u64,
+    pub start_attempts: u32,
+    pub start_retry_backoff: u64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(unused)]
+pub struct Telemetry {
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 #[allow(unused)]
 pub struct Settings {
     pub core: Core,
     pub docker: Docker,
+    pub telemetry: Telemetry,
 }
 
 impl Settings {
@@ -112,12 +324,50 @@ impl Settings {
         let mut config_builder = Config::builder()
             .set_default("core.poll_frequency", 5_000)?
             .set_default("core.poll_timeout", 30_000)?
+            .set_default("core.long_poll", false)?
+            .set_default("core.poll_accept", "application/json")?
+            .set_default("core.long_poll_timeout", 120_000)?
+            .set_default("core.token_refresh_interval", 60_000)?
+            .set_default("core.source", "http")?
+            .set_default("core.result_sinks", vec!["callback"])?
+            .set_default("core.dead_letter_capacity", 100)?
+            .set_default("core.poll_method", "GET")?
+            .set_default("core.callback_timeout", 30_000)?
+            .set_default("core.callback_method", "PUT")?
+            .set_default("core.max_callback_body", 10_485_760)?
+            .set_default("core.out_of_range_progress", "reject")?
             .set_default("core.port", 3000)?
+            .set_default("core.bind_address", "0.0.0.0")?
             .set_default("core.network_name", "foreman")?
+            .set_default("core.network_external", false)?
+            .set_default("core.network_driver", "bridge")?
+            .set_default("core.network_enable_ipv6", false)?
             .set_default("core.job_completion_timeout", 10_000)?
             .set_default("core.job_removal_timeout", 5_000)?
+            .set_default("core.job_start_timeout", 300_000)?
+            .set_default("core.pull_timeout", 600_000)?
+            .set_default("core.reconcile_interval", 5_000)?
+            .set_default("core.finished_job_retention", 3_600_000)?
             .set_default("core.remove_stopped_containers_on_terminate", true)?
-            .set_default("core.max_concurrent_jobs", 12)?;
+            .set_default("core.remove_on_complete_immediately", false)?
+            .set_default("core.auto_remove", false)?
+            .set_default("core.force_remove", false)?
+            .set_default("core.apply_managed_label", true)?
+            .set_default("core.stop_timeout", 0)?
+            .set_default("core.shutdown_stop_order", "oldest_first")?
+            .set_default("core.shutdown_grace_period", 10000)?
+            .set_default("core.shutdown_timeout", 30000)?
+            .set_default("core.read_only_rootfs", false)?
+            .set_default("core.no_new_privileges", false)?
+            .set_default("core.max_concurrent_jobs", 12)?
+            .set_default("core.executor_queue_size", 32)?
+            .set_default("core.tracker_queue_size", 32)?
+            .set_default("core.log_format", "text")?
+            .set_default("docker.circuit_breaker_threshold", 5)?
+            .set_default("docker.circuit_breaker_cooldown", 30_000)?
+            .set_default("docker.start_attempts", 3)?
+            .set_default("docker.start_retry_backoff", 500)?
+            .set_default("telemetry.service_name", "foreman")?;
 
         // Resolve the path to our `foreman.toml` config file (if it exists) and add it
         // to the config builder.
@@ -135,9 +385,220 @@ impl Settings {
             .build()?;
 
         // Deserialize the config into our Settings struct
-        config.try_deserialize()
+        let mut settings: Settings = config.try_deserialize()?;
+
+        // A token read from `core.token_file` takes precedence over `core.token`
+        if let Some(token_file) = &settings.core.token_file {
+            settings.core.token =
+                read_token_file(token_file).map_err(|e| ConfigError::Message(e.to_string()))?;
+        }
+        let has_log_driver_opts = settings
+            .core
+            .log_driver_opts
+            .as_ref()
+            .is_some_and(|opts| !opts.is_empty());
+        if has_log_driver_opts && settings.core.log_driver.is_none() {
+            return Err(ConfigError::Message(
+                "core.log_driver must be set to use core.log_driver_opts".to_string(),
+            ));
+        }
+        if settings.core.result_sinks.contains(&ResultSinkKind::File)
+            && settings.core.result_file.is_none()
+        {
+            return Err(ConfigError::Message(
+                "core.result_file must be set to use the file result sink".to_string(),
+            ));
+        }
+        if settings.core.max_updates_per_second == Some(0) {
+            return Err(ConfigError::Message(
+                "core.max_updates_per_second must be greater than 0".to_string(),
+            ));
+        }
+        if settings.core.tls_cert.is_some() != settings.core.tls_key.is_some() {
+            return Err(ConfigError::Message(
+                "core.tls_cert and core.tls_key must be set together".to_string(),
+            ));
+        }
+        if let Some(subnet) = &settings.core.network_subnet {
+            validate_cidr(subnet).map_err(|e| ConfigError::Message(e.to_string()))?;
+        } else if settings.core.network_gateway.is_some() {
+            return Err(ConfigError::Message(
+                "core.network_subnet must be set to use core.network_gateway".to_string(),
+            ));
+        }
+        if settings.core.shutdown_grace_period > settings.core.shutdown_timeout {
+            return Err(ConfigError::Message(
+                "core.shutdown_grace_period must not exceed core.shutdown_timeout".to_string(),
+            ));
+        }
+        if settings.core.url.is_empty() {
+            return Err(ConfigError::Message(
+                "core.url must contain at least one URL".to_string(),
+            ));
+        }
+        if HeaderValue::from_str(&settings.core.poll_accept).is_err() {
+            return Err(ConfigError::Message(format!(
+                "Invalid core.poll_accept '{}'",
+                settings.core.poll_accept
+            )));
+        }
+        if settings.core.token.is_empty() {
+            return Err(ConfigError::Message(
+                "Either core.token or core.token_file must be set".to_string(),
+            ));
+        }
+        Ok(settings)
+    }
+}
+
+/// Returns an error if `cidr` isn't an IPv4 or IPv6 subnet in CIDR notation e.g., `10.10.0.0/16`.
+pub fn validate_cidr(cidr: &str) -> anyhow::Result<()> {
+    let Some((address, prefix)) = cidr.split_once('/') else {
+        bail!("Invalid subnet '{}', expected CIDR notation", cidr);
+    };
+    let max_prefix = match address.parse::<IpAddr>() {
+        Ok(IpAddr::V4(_)) => 32,
+        Ok(IpAddr::V6(_)) => 128,
+        Err(_) => bail!("Invalid subnet '{}', bad address '{}'", cidr, address),
+    };
+    match prefix.parse::<u8>() {
+        Ok(prefix) if prefix <= max_prefix => Ok(()),
+        _ => bail!("Invalid subnet '{}', bad prefix length '{}'", cidr, prefix),
+    }
+}
+
+/// Read the control server token from the file at path, trimming surrounding whitespace.
+/// Returns an error if the file can't be read or is empty.
+pub fn read_token_file(path: &Path) -> anyhow::Result<String> {
+    let token = fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read token file {}: {}", path.display(), e))?;
+    let token = token.trim();
+    if token.is_empty() {
+        bail!("Token file {} is empty", path.display());
+    }
+    Ok(token.to_string())
+}
+
+/// Config file path passed via the `--config` command line argument.
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use the config file at `path`, taking precedence over `FOREMAN_CONFIG` and the default search
+/// paths. Returns an error if the file does not exist.
+pub fn set_config_path(path: PathBuf) -> anyhow::Result<()> {
+    if !path.exists() {
+        bail!("Config file {} does not exist", path.display());
     }
+    CONFIG_PATH
+        .set(path)
+        .map_err(|_| anyhow::anyhow!("Config file path already set"))
 }
 
 pub static SETTINGS: LazyLock<Settings> =
     LazyLock::new(|| Settings::new().expect("Failed to load foreman settings"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label_map(pairs: &[(&str, &str)]) -> LabelMap {
+        LabelMap(
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_label_map_round_trip() {
+        let labels = label_map(&[
+            ("cpu", "4"),
+            ("a=b", "c=d"),
+            ("list", "x,y,z"),
+            ("with space", "hello world"),
+            ("mixed, =key", "=,= ,"),
+            ("empty", ""),
+        ]);
+        let header: String = (&labels).into();
+        assert_eq!(header.parse::<LabelMap>().unwrap(), labels);
+    }
+
+    #[test]
+    fn test_parse_label_map() {
+        assert_eq!("".parse::<LabelMap>().unwrap(), LabelMap::new());
+        assert_eq!(
+            "queue=high-priority,gpu=true".parse::<LabelMap>().unwrap(),
+            label_map(&[("queue", "high-priority"), ("gpu", "true")])
+        );
+        assert!("queue".parse::<LabelMap>().is_err());
+    }
+
+    #[test]
+    fn test_validate_cidr() {
+        assert!(validate_cidr("10.10.0.0/16").is_ok());
+        assert!(validate_cidr("fd00:abcd::/64").is_ok());
+        assert!(validate_cidr("10.10.0.0").is_err());
+        assert!(validate_cidr("10.10.0.0/33").is_err());
+        assert!(validate_cidr("10.10.0/16").is_err());
+        assert!(validate_cidr("fd00::/129").is_err());
+    }
+
+    #[test]
+    fn test_read_token_file() {
+        let dir = env::temp_dir().join(format!("foreman-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("token");
+        fs::write(&path, "  MY-SECRET-TOKEN\n").unwrap();
+        assert_eq!(read_token_file(&path).unwrap(), "MY-SECRET-TOKEN");
+
+        fs::write(&path, " \n").unwrap();
+        assert!(read_token_file(&path).is_err());
+
+        assert!(read_token_file(&dir.join("missing")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_serialize_label_map() {
+        let labels = label_map(&[("a=b", "c,d")]);
+        assert_eq!(
+            serde_json::to_value(&labels).unwrap(),
+            serde_json::json!({ "a=b": "c,d" })
+        );
+    }
+
+    #[test]
+    fn test_redact() {
+        let redacted = redact("secret", serde_json::value::Serializer).unwrap();
+        assert_eq!(redacted, serde_json::json!("<redacted>"));
+        let empty = redact("", serde_json::value::Serializer).unwrap();
+        assert_eq!(empty, serde_json::json!(""));
+    }
+
+    #[test]
+    fn test_string_or_list() {
+        #[derive(Deserialize)]
+        struct Urls {
+            #[serde(deserialize_with = "string_or_list")]
+            url: Vec<String>,
+        }
+        let urls: Urls = serde_json::from_value(serde_json::json!({ "url": "http://a" })).unwrap();
+        assert_eq!(urls.url, vec!["http://a"]);
+        let urls: Urls =
+            serde_json::from_value(serde_json::json!({ "url": ["http://a", "http://b"] })).unwrap();
+        assert_eq!(urls.url, vec!["http://a", "http://b"]);
+    }
+
+    #[test]
+    fn test_poll_query_param_value() {
+        let labels = label_map(&[("cpu", "4")]);
+        assert_eq!(PollQueryParam::Slots.value(Some(&labels), 3), "3");
+        assert_eq!(PollQueryParam::Labels.value(Some(&labels), 3), "cpu=4");
+        assert_eq!(PollQueryParam::Labels.value(None, 3), "");
+        assert_eq!(
+            PollQueryParam::Version.value(None, 3),
+            env!("CARGO_PKG_VERSION")
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, HeaderValue},
+};
+use log::info;
+use tracing::Span;
+
+use super::ResultSink;
+use crate::{
+    job::{render_callback_url, DockerJob},
+    settings::SETTINGS,
+    tracking::JobUpdate,
+    USER_AGENT,
+};
+
+/// Forwards updates to the job's `callbackUrl` as-is.
+pub struct CallbackSink {
+    http_client: reqwest::Client,
+}
+
+impl CallbackSink {
+    pub fn new(http_client: reqwest::Client) -> Self {
+        CallbackSink { http_client }
+    }
+}
+
+impl ResultSink for CallbackSink {
+    async fn publish(
+        &self,
+        job: &DockerJob,
+        span: &Span,
+        update: &JobUpdate,
+        headers: &HeaderMap,
+        body: &Bytes,
+    ) -> Result<()> {
+        let job_id = job.id.as_str();
+        let callback_url = render_callback_url(&job.callback_url, |name| match name {
+            "id" => job.id.clone(),
+            _ => format!("{:?}", update.status).to_lowercase(),
+        })?;
+        let method: reqwest::Method = job
+            .callback_method
+            .unwrap_or(SETTINGS.core.callback_method)
+            .into();
+        info!(job_id = job_id; "Sending {} request to callback URL {}", method, callback_url);
+        let mut headers = headers.clone();
+        headers.insert("user-agent", HeaderValue::from_str(&USER_AGENT).unwrap());
+        // Echo the control server's trace id back on the callback
+        if let Some(trace_id) = job
+            .trace_id
+            .as_ref()
+            .and_then(|t| HeaderValue::from_str(t).ok())
+        {
+            headers.insert("x-foreman-trace-id", trace_id);
+        }
+        let resp = self
+            .http_client
+            .request(method, &callback_url)
+            .timeout(Duration::from_millis(SETTINGS.core.callback_timeout))
+            .headers(headers)
+            .body(body.clone())
+            .send()
+            .await?;
+        let status_code = resp.status();
+        info!(job_id = job_id; "- Status code {}", status_code);
+        span.in_scope(|| {
+            tracing::info!(status_code = status_code.as_u16(), "callback delivered");
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use axum::{body::Bytes, http::HeaderMap};
+use serde_json::json;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt, sync::Mutex};
+use tracing::Span;
+
+use super::ResultSink;
+use crate::{job::DockerJob, tracking::JobUpdate};
+
+/// Appends updates to a file as newline delimited JSON.
+pub struct FileSink {
+    path: PathBuf,
+    /// Serializes writes so concurrent updates aren't interleaved.
+    lock: Mutex<()>,
+}
+
+impl FileSink {
+    pub fn new(path: PathBuf) -> Self {
+        FileSink {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl ResultSink for FileSink {
+    async fn publish(
+        &self,
+        job: &DockerJob,
+        _span: &Span,
+        update: &JobUpdate,
+        _headers: &HeaderMap,
+        body: &Bytes,
+    ) -> Result<()> {
+        let timestamp = OffsetDateTime::now_utc().format(&Rfc3339)?;
+        let mut line = json!({
+            "job_id": job.id,
+            "status": update.status,
+            "progress": update.progress,
+            "exit_code": update.exit_code,
+            "result": update.result,
+            // Bodies are job-specific, so are only included when they are text
+            "body": std::str::from_utf8(body).ok().filter(|body| !body.is_empty()),
+            "timestamp": timestamp,
+        })
+        .to_string();
+        line.push('\n');
+
+        let _guard = self.lock.lock().await;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        // Writes complete in the background, so wait for them before releasing the lock
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{job::Job, tracking::JobStatus};
+
+    #[tokio::test]
+    async fn test_publish_appends_json_lines() {
+        let path =
+            std::env::temp_dir().join(format!("foreman-results-{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sink = FileSink::new(path.clone());
+        let Job::Docker(job) = serde_json::from_str(
+            r#"{
+                "id": "123abc",
+                "image": "alpine",
+                "body": null,
+                "callbackUrl": "http://localhost"
+            }"#,
+        )
+        .unwrap();
+        let update = JobUpdate {
+            status: JobStatus::Completed,
+            progress: 1.0,
+            exit_code: Some(0),
+            result: None,
+        };
+        for body in ["done", ""] {
+            sink.publish(
+                &job,
+                &Span::none(),
+                &update,
+                &HeaderMap::new(),
+                &Bytes::from(body),
+            )
+            .await
+            .unwrap();
+        }
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["job_id"], "123abc");
+        assert_eq!(lines[0]["status"], "COMPLETED");
+        assert_eq!(lines[0]["exit_code"], 0);
+        assert_eq!(lines[0]["body"], "done");
+        assert!(lines[1]["body"].is_null());
+    }
+}
@@ -0,0 +1,46 @@
+mod callback;
+mod file;
+
+pub use callback::*;
+pub use file::*;
+
+use anyhow::Result;
+use axum::{body::Bytes, http::HeaderMap};
+use tracing::Span;
+
+use crate::{job::DockerJob, tracking::JobUpdate};
+
+pub trait ResultSink {
+    /// Publish an update reported by a job. `headers` and `body` are those of the job's request
+    /// to foreman.
+    async fn publish(
+        &self,
+        job: &DockerJob,
+        span: &Span,
+        update: &JobUpdate,
+        headers: &HeaderMap,
+        body: &Bytes,
+    ) -> Result<()>;
+}
+
+/// Any of foreman's result sinks, as configured by `core.result_sinks`.
+pub enum Sink {
+    Callback(CallbackSink),
+    File(FileSink),
+}
+
+impl ResultSink for Sink {
+    async fn publish(
+        &self,
+        job: &DockerJob,
+        span: &Span,
+        update: &JobUpdate,
+        headers: &HeaderMap,
+        body: &Bytes,
+    ) -> Result<()> {
+        match self {
+            Sink::Callback(sink) => sink.publish(job, span, update, headers, body).await,
+            Sink::File(sink) => sink.publish(job, span, update, headers, body).await,
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use log::{error, warn};
+use reqwest::header::{HeaderMap, ACCEPT, CONTENT_TYPE};
+use serde_json::{json, Value};
+
+use super::JobSource;
+use crate::{
+    job::{Job, NdjsonJobs, NDJSON_CONTENT_TYPE},
+    metrics::{PollMetrics, PollOutcome},
+    settings::{LabelMap, PollMethod, SETTINGS},
+};
+
+/// A control server's response to a poll.
+enum PollResponse {
+    /// A batch of jobs, received in full.
+    Jobs(Vec<Job>),
+    /// Jobs streamed as newline delimited JSON, which are yet to be read.
+    Stream(reqwest::Response),
+}
+
+/// Fetches jobs by polling the control server at `core.url` over HTTP.
+pub struct HttpJobSource {
+    http_client: reqwest::Client,
+    /// Control server token, which may be rotated while running.
+    token: Arc<RwLock<String>>,
+    metrics: Arc<Mutex<PollMetrics>>,
+    /// Headers sent on every poll.
+    headers: HeaderMap,
+    body: Value,
+    timeout: Duration,
+    /// Index of the control server URL which most recently responded.
+    healthy_url: usize,
+    /// The response of the latest poll, when it's jobs are still being streamed.
+    stream: Option<(reqwest::Response, NdjsonJobs)>,
+}
+
+impl HttpJobSource {
+    pub fn new(
+        http_client: reqwest::Client,
+        token: Arc<RwLock<String>>,
+        metrics: Arc<Mutex<PollMetrics>>,
+    ) -> Self {
+        let settings = &*SETTINGS;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ACCEPT,
+            settings
+                .core
+                .poll_accept
+                .parse()
+                .expect("Failed to parse core.poll_accept into header value"),
+        );
+        if let Some(labels) = &settings.core.labels {
+            let labels_string: String = labels.into();
+            headers.insert(
+                "x-foreman-labels",
+                labels_string
+                    .parse()
+                    .expect("Failed to parse labels into header value"),
+            );
+        }
+        // When long polling the control server holds requests open until a job is available
+        let timeout = if settings.core.long_poll {
+            Duration::from_millis(settings.core.long_poll_timeout)
+        } else {
+            Duration::from_millis(settings.core.poll_timeout.into())
+        };
+        let body = json!({
+            "labels": settings.core.labels.as_ref().unwrap_or(&LabelMap::new()),
+        });
+        HttpJobSource {
+            http_client,
+            token,
+            metrics,
+            headers,
+            body,
+            timeout,
+            healthy_url: 0,
+            stream: None,
+        }
+    }
+
+    async fn poll(&self, url: &str, query: &[(&str, String)]) -> Result<PollResponse> {
+        let request = match SETTINGS.core.poll_method {
+            PollMethod::Get => self.http_client.get(url),
+            PollMethod::Post => self.http_client.post(url).json(&self.body),
+        };
+        let token = self.token.read().unwrap().clone();
+        let resp = request
+            .query(query)
+            .timeout(self.timeout)
+            .headers(self.headers.clone())
+            .header("Authorization", format!("Bearer {}", token))
+            .send()
+            .await?;
+        // Jobs streamed as NDJSON are dispatched as each line arrives, rather than once the whole
+        // response is received
+        let is_ndjson = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|hv| hv.to_str().ok())
+            .is_some_and(|ct| ct.starts_with(NDJSON_CONTENT_TYPE));
+        if is_ndjson {
+            return Ok(PollResponse::Stream(resp.error_for_status()?));
+        }
+        let jobs = resp.json::<Vec<Job>>().await?;
+        Ok(PollResponse::Jobs(jobs))
+    }
+}
+
+/// Skip jobs which couldn't be decoded, logging the error.
+fn valid_jobs(jobs: Vec<Result<Job>>) -> Vec<Job> {
+    jobs.into_iter()
+        .filter_map(|job| {
+            job.map_err(|e| error!("Skipping invalid job from control server: {}", e))
+                .ok()
+        })
+        .collect()
+}
+
+impl JobSource for HttpJobSource {
+    async fn fetch(&mut self, free_slots: u64) -> Result<Vec<Job>> {
+        // Abandon any jobs still being streamed from the previous poll
+        self.stream = None;
+
+        let settings = &*SETTINGS;
+        let query: Vec<(&str, String)> = settings
+            .core
+            .poll_query_params
+            .iter()
+            .map(|param| {
+                (
+                    param.name(),
+                    param.value(settings.core.labels.as_ref(), free_slots),
+                )
+            })
+            .collect();
+
+        // Starting with the URL which last responded, try each URL in turn until one responds
+        let urls = &settings.core.url;
+        let mut result: Result<PollResponse> = Err(anyhow!("No control server URLs"));
+        for offset in 0..urls.len() {
+            let index = (self.healthy_url + offset) % urls.len();
+            let url = &urls[index];
+            let started_at = Instant::now();
+            result = self.poll(url, &query).await;
+            let outcome = match &result {
+                Ok(PollResponse::Jobs(jobs)) if jobs.is_empty() => PollOutcome::Empty,
+                Ok(_) => PollOutcome::Ok,
+                Err(_) => PollOutcome::Error,
+            };
+            self.metrics
+                .lock()
+                .unwrap()
+                .record(started_at.elapsed(), outcome);
+            match &result {
+                Ok(_) => {
+                    if index != self.healthy_url {
+                        warn!(
+                            "Failed over from control server {} to {}",
+                            urls[self.healthy_url], url
+                        );
+                        self.healthy_url = index;
+                    }
+                    break;
+                }
+                Err(e) if urls.len() > 1 => {
+                    warn!("Failed to poll control server {}: {}", url, e);
+                }
+                Err(_) => {}
+            }
+        }
+
+        match result? {
+            PollResponse::Jobs(jobs) => Ok(jobs),
+            PollResponse::Stream(resp) => {
+                self.stream = Some((resp, NdjsonJobs::default()));
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    async fn fetch_more(&mut self) -> Result<Option<Vec<Job>>> {
+        let Some((resp, ndjson)) = &mut self.stream else {
+            return Ok(None);
+        };
+        match resp.chunk().await {
+            Ok(Some(chunk)) => Ok(Some(valid_jobs(ndjson.push(&chunk)))),
+            Ok(None) => {
+                let (_, ndjson) = self.stream.take().unwrap();
+                Ok(Some(valid_jobs(ndjson.finish().into_iter().collect())))
+            }
+            Err(e) => {
+                self.stream = None;
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Acknowledge receipt of a job to `core.ack_url`, if set.
+    async fn ack(&self, job_id: &str) -> Result<()> {
+        let Some(ack_url) = &SETTINGS.core.ack_url else {
+            return Ok(());
+        };
+        let token = self.token.read().unwrap().clone();
+        self.http_client
+            .post(ack_url)
+            .timeout(Duration::from_millis(SETTINGS.core.poll_timeout.into()))
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({ "id": job_id }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,20 @@
+mod http;
+
+pub use http::*;
+
+use anyhow::Result;
+
+use crate::job::Job;
+
+pub trait JobSource {
+    /// Fetch the next batch of jobs. `free_slots` is the number of additional jobs foreman can
+    /// currently run.
+    async fn fetch(&mut self, free_slots: u64) -> Result<Vec<Job>>;
+    /// Fetch further jobs of the latest batch which are still arriving e.g., streamed by the
+    /// control server. Returns `None` once the batch is complete.
+    async fn fetch_more(&mut self) -> Result<Option<Vec<Job>>> {
+        Ok(None)
+    }
+    /// Acknowledge receipt of a job so it is not fetched again.
+    async fn ack(&self, job_id: &str) -> Result<()>;
+}
@@ -0,0 +1,45 @@
+use anyhow::Result;
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use tracing::Span;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+use crate::settings::Telemetry;
+
+/// Initialize OpenTelemetry tracing, exporting spans to the configured OTLP endpoint.
+/// Does nothing if `telemetry.otlp_endpoint` is not set.
+pub fn init(telemetry: &Telemetry) -> Result<()> {
+    let Some(endpoint) = &telemetry.otlp_endpoint else {
+        return Ok(());
+    };
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            telemetry.service_name.clone(),
+        )]))
+        .build();
+    let tracer = provider.tracer("foreman");
+    opentelemetry::global::set_tracer_provider(provider);
+
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()?;
+    Ok(())
+}
+
+/// Flush any pending spans and shut down the tracer provider.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Create the span which tracks a job from insertion until it reaches a terminal status.
+pub fn job_span(job_id: &str) -> Span {
+    tracing::info_span!("job", job.id = job_id)
+}
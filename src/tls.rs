@@ -0,0 +1,85 @@
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
+use axum::Router;
+use hyper::server::conn::http1;
+use hyper_util::{rt::TokioIo, service::TowerToHyperService};
+use log::warn;
+use tokio::net::TcpListener;
+use tokio_rustls::{
+    rustls::{
+        crypto::ring,
+        pki_types::{CertificateDer, PrivateKeyDer},
+        ServerConfig,
+    },
+    TlsAcceptor,
+};
+
+/// Build a TLS server config from a PEM encoded certificate chain and private key.
+pub fn load_server_config(cert_path: &Path, key_path: &Path) -> Result<Arc<ServerConfig>> {
+    let certs: Vec<CertificateDer> = rustls_pemfile::certs(&mut open(cert_path)?)
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("Failed to read certificates from {}", cert_path.display()))?;
+    if certs.is_empty() {
+        return Err(anyhow!("No certificates found in {}", cert_path.display()));
+    }
+    let key: PrivateKeyDer = rustls_pemfile::private_key(&mut open(key_path)?)
+        .with_context(|| format!("Failed to read private key from {}", key_path.display()))?
+        .ok_or_else(|| anyhow!("No private key found in {}", key_path.display()))?;
+    let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(Arc::new(config))
+}
+
+fn open(path: &Path) -> Result<BufReader<File>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    Ok(BufReader::new(file))
+}
+
+/// Serve `app` over HTTPS on connections accepted from `listener`.
+pub async fn serve(listener: TcpListener, app: Router, config: Arc<ServerConfig>) -> Result<()> {
+    let acceptor = TlsAcceptor::from(config);
+    loop {
+        let (stream, peer_addr) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let service = TowerToHyperService::new(app.clone());
+        // Each connection is handled separately, so a slow handshake doesn't block others
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("TLS handshake with {} failed: {}", peer_addr, e);
+                    return;
+                }
+            };
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .with_upgrades()
+                .await
+            {
+                warn!("Error serving connection from {}: {}", peer_addr, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_server_config_errors() {
+        let dir = std::env::temp_dir().join(format!("foreman-tls-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let empty = dir.join("empty.pem");
+        std::fs::write(&empty, "").unwrap();
+
+        assert!(load_server_config(&dir.join("missing.pem"), &empty).is_err());
+        let e = load_server_config(&empty, &empty).unwrap_err();
+        assert!(e.to_string().contains("No certificates found"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
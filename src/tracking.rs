@@ -1,20 +1,40 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, VecDeque},
     str::FromStr,
     sync::{Arc, Mutex},
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::{bail, Ok, Result};
-use serde::Deserialize;
-use tokio::sync::{mpsc::Sender, oneshot};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::sync::{
+    broadcast,
+    mpsc::{error::SendTimeoutError, Sender},
+    oneshot, Notify,
+};
+use tracing::Span;
 
 use crate::{
-    job::{DockerJob, Job},
-    settings::SETTINGS,
+    job::{validate_job_id, DockerJob, Job},
+    settings::{OutOfRangeProgress, SETTINGS},
 };
 
-#[derive(Debug, Deserialize, PartialEq, Clone)]
+/// The status of a tracked job. A job normally flows through the following statuses:
+///
+/// 1. `Pending` - the job was received from the control server and it's container created.
+/// 2. `Running` - the job's container fetched the job via `GET /job/:job_id`. The container may
+///    report progress by sending further `running` updates.
+/// 3. `Completed` - the job's container reported it's work is done. The job can no longer be
+///    fetched and the lifecycle task stops the container.
+/// 4. `Stopped` - the job's container was stopped. After `core.job_removal_timeout` the lifecycle
+///    task removes the container.
+/// 5. `Finished` - the job's container was removed.
+///
+/// A job is also `Stopped` (skipping `Completed`) if it times out or exceeds it's maximum
+/// lifetime, and `Failed` if it's container never fetched the job or could not be created.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum JobStatus {
     Pending,
@@ -22,6 +42,18 @@ pub enum JobStatus {
     Completed,
     Stopped,
     Finished,
+    Failed,
+}
+
+impl JobStatus {
+    /// Whether the job may be fetched by it's container i.e., the job's work is not yet done.
+    pub fn is_fetchable(&self) -> bool {
+        matches!(self, JobStatus::Pending | JobStatus::Running)
+    }
+    /// Whether this is a terminal status i.e., the job will not change status again.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, JobStatus::Finished | JobStatus::Failed)
+    }
 }
 
 impl FromStr for JobStatus {
@@ -35,21 +67,218 @@ impl FromStr for JobStatus {
             "COMPLETED" => JobStatus::Completed,
             "STOPPED" => JobStatus::Stopped,
             "FINISHED" => JobStatus::Finished,
+            "FAILED" => JobStatus::Failed,
             _ => bail!("Unknown job status"),
         };
         Ok(status)
     }
 }
 
+/// Why a job became `Failed`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    /// The job couldn't be prepared e.g. it's image couldn't be pulled.
+    PrepareFailed,
+    /// The job's container couldn't be created or started.
+    StartFailed,
+    /// The job's container didn't fetch the job within `core.job_start_timeout`.
+    StartTimeout,
+    /// The job's container was force removed via the API.
+    ForceRemoved,
+    /// The job's container was killed after exceeding it's memory limit.
+    OomKilled,
+}
+
+impl FailureReason {
+    pub const ALL: [FailureReason; 5] = [
+        FailureReason::PrepareFailed,
+        FailureReason::StartFailed,
+        FailureReason::StartTimeout,
+        FailureReason::ForceRemoved,
+        FailureReason::OomKilled,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureReason::PrepareFailed => "prepare_failed",
+            FailureReason::StartFailed => "start_failed",
+            FailureReason::StartTimeout => "start_timeout",
+            FailureReason::ForceRemoved => "force_removed",
+            FailureReason::OomKilled => "oom_killed",
+        }
+    }
+}
+
+/// Returns an error unless `progress` is within the range 0.0 to 1.0 (inclusive).
+pub fn validate_progress(progress: f64) -> Result<()> {
+    if !(0.0..=1.0).contains(&progress) {
+        bail!("Progress must be between 0.0 and 1.0, got {}", progress);
+    }
+    Ok(())
+}
+
+/// Returns `progress` if it's within the range 0.0 to 1.0 (inclusive). Out of range progress is
+/// either rejected or clamped to the range, depending on `out_of_range`. NaN is always rejected.
+pub fn normalize_progress(progress: f64, out_of_range: OutOfRangeProgress) -> Result<f64> {
+    if out_of_range == OutOfRangeProgress::Clamp && !progress.is_nan() {
+        return Ok(progress.clamp(0.0, 1.0));
+    }
+    validate_progress(progress)?;
+    Ok(progress)
+}
+
+/// An update reported by a job's container.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JobUpdate {
+    pub status: JobStatus,
+    #[serde(default)]
+    pub progress: f64,
+    pub exit_code: Option<i64>,
+    pub result: Option<String>,
+}
+
+/// Coalesce a batch of updates, ordered oldest first, into the latest update. An exit code or
+/// result reported by an earlier update is kept unless the latest update reports it's own.
+pub fn coalesce_updates(
+    mut updates: Vec<JobUpdate>,
+    out_of_range: OutOfRangeProgress,
+) -> Result<JobUpdate> {
+    for update in &mut updates {
+        update.progress = normalize_progress(update.progress, out_of_range)?;
+    }
+    let mut updates = updates.into_iter();
+    let Some(mut latest) = updates.next() else {
+        bail!("Expected at least one update");
+    };
+    for update in updates {
+        latest = JobUpdate {
+            exit_code: update.exit_code.or(latest.exit_code),
+            result: update.result.or(latest.result),
+            ..update
+        };
+    }
+    Ok(latest)
+}
+
+/// A job's current status and progress.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatusUpdate {
+    pub job_id: String,
+    pub status: JobStatus,
+    pub progress: f64,
+    pub failure_reason: Option<FailureReason>,
+}
+
+impl From<&JobEvent> for JobStatusUpdate {
+    fn from(event: &JobEvent) -> Self {
+        JobStatusUpdate {
+            job_id: event.job_id.clone(),
+            status: event.status.clone(),
+            progress: event.progress,
+            failure_reason: event.failure_reason,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobEventKind {
+    /// The job was received from the control server and is now tracked.
+    Inserted,
+    /// The job's status and/or progress changed.
+    StatusChanged,
+    /// The job's container was removed i.e., the job transitioned to `Finished`.
+    Removed,
+}
+
+/// A job lifecycle event, broadcast by the `JobTracker`.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobEvent {
+    pub kind: JobEventKind,
+    pub job_id: String,
+    pub old_status: Option<JobStatus>,
+    pub status: JobStatus,
+    pub progress: f64,
+    /// See `TrackedJob::running_duration`.
+    pub running_duration_ms: u64,
+    /// See `TrackedJob::total_duration`.
+    pub total_duration_ms: u64,
+    /// See `TrackedJob::queued_duration`.
+    pub queued_duration_ms: u64,
+    /// See `TrackedJob::execution_duration`.
+    pub execution_duration_ms: Option<u64>,
+    pub exit_code: Option<i64>,
+    pub result: Option<String>,
+    pub failure_reason: Option<FailureReason>,
+    /// RFC3339 timestamp of when the event occurred.
+    pub timestamp: String,
+}
+
+impl JobEvent {
+    fn new(kind: JobEventKind, old_status: Option<JobStatus>, tracked_job: &TrackedJob) -> Self {
+        JobEvent {
+            kind,
+            job_id: tracked_job.job.id().to_owned(),
+            old_status,
+            status: tracked_job.status.clone(),
+            progress: tracked_job.progress,
+            running_duration_ms: tracked_job.running_duration().as_millis() as u64,
+            total_duration_ms: tracked_job.total_duration().as_millis() as u64,
+            queued_duration_ms: tracked_job.queued_duration().as_millis() as u64,
+            execution_duration_ms: tracked_job
+                .execution_duration()
+                .map(|duration| duration.as_millis() as u64),
+            exit_code: tracked_job.exit_code,
+            result: tracked_job.result.clone(),
+            failure_reason: tracked_job.failure_reason,
+            timestamp: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// IDs of tracked jobs grouped by the states the lifecycle task acts upon,
+/// allowing them all to be retrieved in a single round-trip to the tracker.
+#[derive(Debug, Default)]
+pub struct JobIdBuckets {
+    pub pending: Vec<String>,
+    pub completed: Vec<String>,
+    pub timed_out: Vec<String>,
+    pub stopped_and_expired: Vec<String>,
+    pub pending_and_expired: Vec<String>,
+    pub lifetime_exceeded: Vec<String>,
+    pub running: Vec<String>,
+    pub stopped: Vec<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct TrackedJob {
     job: Job,
     status: JobStatus,
     progress: f64,
     start_time: SystemTime,
+    /// When the job's container was started, after any image pull.
+    container_started_time: Option<SystemTime>,
+    /// When the job first transitioned to `Running` i.e., when it's container fetched the job.
+    running_time: Option<SystemTime>,
     completed_time: Option<SystemTime>,
     stopped_time: Option<SystemTime>,
     finished_time: Option<SystemTime>,
+    /// Overrides the `core.job_completion_timeout` setting for this job.
+    timeout: Option<Duration>,
+    /// Exit code reported by the job's container, either in an update or a batch of updates, or
+    /// by foreman when the container was OOM killed.
+    exit_code: Option<i64>,
+    /// Result reported by the job's container, either in an update or a batch of updates.
+    result: Option<String>,
+    /// Why the job failed, if it did.
+    failure_reason: Option<FailureReason>,
+    /// When each update from the job's container within the last second was received.
+    update_times: VecDeque<Instant>,
+    span: Span,
 }
 
 impl TrackedJob {
@@ -60,32 +289,166 @@ impl TrackedJob {
     pub fn status(&self) -> &JobStatus {
         &self.status
     }
+
+    pub fn progress(&self) -> f64 {
+        self.progress
+    }
+
+    pub fn exit_code(&self) -> Option<i64> {
+        self.exit_code
+    }
+
+    pub fn result(&self) -> Option<&str> {
+        self.result.as_deref()
+    }
+
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// When the job was received.
+    pub fn start_time(&self) -> SystemTime {
+        self.start_time
+    }
+
+    pub fn container_started_time(&self) -> Option<SystemTime> {
+        self.container_started_time
+    }
+
+    pub fn failure_reason(&self) -> Option<FailureReason> {
+        self.failure_reason
+    }
+
+    /// Record an update from the job's container at `now`, returning `false` without recording it
+    /// if `max_per_second` updates have already been received within the last second.
+    pub fn record_update(&mut self, max_per_second: u32, now: Instant) -> bool {
+        while self
+            .update_times
+            .front()
+            .is_some_and(|time| now.duration_since(*time) >= Duration::from_secs(1))
+        {
+            self.update_times.pop_front();
+        }
+        if self.update_times.len() >= max_per_second as usize {
+            return false;
+        }
+        self.update_times.push_back(now);
+        true
+    }
+
+    /// Time from when the job was received until it completed or was stopped, or until now if
+    /// it's still pending or running. For jobs stopped due to a timeout this is the
+    /// time-to-timeout.
+    pub fn running_duration(&self) -> Duration {
+        let end = self
+            .completed_time
+            .or(self.stopped_time)
+            .or(self.finished_time)
+            .unwrap_or_else(SystemTime::now);
+        end.duration_since(self.start_time).unwrap_or_default()
+    }
+
+    /// Time from when the job was received until it's container started, or until now if it hasn't
+    /// yet. Includes time queued for the executor and pulling the job's image.
+    pub fn queued_duration(&self) -> Duration {
+        self.container_started_time
+            .unwrap_or_else(SystemTime::now)
+            .duration_since(self.start_time)
+            .unwrap_or_default()
+    }
+
+    /// Time from when the job's container started until it completed or was stopped, or until now
+    /// if it's still running. `None` if the container hasn't started.
+    pub fn execution_duration(&self) -> Option<Duration> {
+        let container_started_time = self.container_started_time?;
+        let end = self
+            .completed_time
+            .or(self.stopped_time)
+            .or(self.finished_time)
+            .unwrap_or_else(SystemTime::now);
+        Some(
+            end.duration_since(container_started_time)
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Time from when the job was received until it reached a terminal status, or until now if
+    /// it hasn't yet.
+    pub fn total_duration(&self) -> Duration {
+        self.finished_time
+            .unwrap_or_else(SystemTime::now)
+            .duration_since(self.start_time)
+            .unwrap_or_default()
+    }
 }
 
+/// Number of events buffered for slow subscribers before they start missing events.
+const EVENTS_CAPACITY: usize = 256;
+
 pub struct JobTracker {
-    jobs: HashMap<String, Arc<Mutex<TrackedJob>>>,
+    /// Ordered by id, so queries over jobs return them in a stable order.
+    jobs: BTreeMap<String, Arc<Mutex<TrackedJob>>>,
+    slot_freed: Arc<Notify>,
+    events: broadcast::Sender<JobEvent>,
 }
 
 impl JobTracker {
     pub fn new() -> Self {
         JobTracker {
-            jobs: HashMap::new(),
+            jobs: BTreeMap::new(),
+            slot_freed: Arc::new(Notify::new()),
+            events: broadcast::channel(EVENTS_CAPACITY).0,
         }
     }
 
-    pub fn insert(&mut self, job: Job) {
-        let Job::Docker(DockerJob { ref id, .. }) = job;
+    /// Returns a `broadcast::Sender` on which every job event is sent.
+    /// Call `subscribe` on it to receive events from that point onwards.
+    pub fn events(&self) -> broadcast::Sender<JobEvent> {
+        self.events.clone()
+    }
+
+    fn emit(&self, event: JobEvent) {
+        // Ignore send errors which only indicate there are no subscribers
+        let _ = self.events.send(event);
+    }
+
+    /// Returns a `Notify` which is signalled whenever a running job transitions to another
+    /// status, freeing up a concurrency slot.
+    pub fn slot_freed(&self) -> Arc<Notify> {
+        self.slot_freed.clone()
+    }
+
+    /// Track a new job. Returns `false` without modifying the existing job if a job with the same
+    /// id is already tracked, or an error if the job's id is invalid.
+    pub fn insert(&mut self, job: Job, span: Span) -> Result<bool> {
+        let Job::Docker(DockerJob {
+            ref id, timeout, ..
+        }) = job;
+        validate_job_id(id)?;
+        if self.jobs.contains_key(id) {
+            return Ok(false);
+        }
         let job_id = id.to_owned();
         let tracked_job = TrackedJob {
             job,
             status: JobStatus::Pending,
             progress: 0.0,
             start_time: SystemTime::now(),
+            container_started_time: None,
+            running_time: None,
             completed_time: None,
             stopped_time: None,
             finished_time: None,
+            timeout: timeout.map(Duration::from_millis),
+            exit_code: None,
+            result: None,
+            failure_reason: None,
+            update_times: VecDeque::new(),
+            span,
         };
+        self.emit(JobEvent::new(JobEventKind::Inserted, None, &tracked_job));
         self.jobs.insert(job_id, Arc::new(Mutex::new(tracked_job)));
+        Ok(true)
     }
 
     pub fn get_job(&self, id: &str) -> Option<&Arc<Mutex<TrackedJob>>> {
@@ -98,30 +461,116 @@ impl JobTracker {
         status: JobStatus,
         progress: Option<f64>,
     ) -> Result<()> {
-        // TODO: Prevent transition between certain states e.g., from Completed to Running is invalid
+        // TODO: Prevent transition between certain states e.g., from Completed to Running is
+        // invalid
+        if let Some(progress) = progress {
+            validate_progress(progress)?;
+        }
         if let Some(tracked_job) = self.jobs.get(id) {
             let mut tracked_job = tracked_job.lock().unwrap();
             match status {
+                JobStatus::Running => {
+                    tracked_job.running_time.get_or_insert_with(SystemTime::now);
+                }
                 JobStatus::Completed => {
                     tracked_job.completed_time = Some(SystemTime::now());
                 }
                 JobStatus::Stopped => {
                     tracked_job.stopped_time = Some(SystemTime::now());
                 }
-                JobStatus::Finished => {
+                JobStatus::Finished | JobStatus::Failed => {
                     tracked_job.finished_time = Some(SystemTime::now());
                 }
                 _ => {}
             }
-            tracked_job.status = status;
+            tracked_job.span.in_scope(|| {
+                tracing::info!(status = ?status, progress, "job status updated");
+            });
+            if tracked_job.status == JobStatus::Running && status != JobStatus::Running {
+                self.slot_freed.notify_one();
+            }
+            // Decreasing progress is often a sign the job's process restarted
+            if let Some(progress) = progress {
+                if tracked_job.status == JobStatus::Running && progress < tracked_job.progress {
+                    warn!(
+                        job_id = id;
+                        "Progress of job {} went backwards from {} to {}",
+                        id,
+                        tracked_job.progress,
+                        progress
+                    );
+                }
+            }
+            let old_status = std::mem::replace(&mut tracked_job.status, status);
             if let Some(progress) = progress {
                 tracked_job.progress = progress;
             }
+            let kind = if tracked_job.status == JobStatus::Finished {
+                JobEventKind::Removed
+            } else {
+                JobEventKind::StatusChanged
+            };
+            self.emit(JobEvent::new(kind, Some(old_status), &tracked_job));
+            // Close the job's span once it reaches a terminal status
+            if tracked_job.status.is_terminal() {
+                tracked_job.span.in_scope(|| {
+                    tracing::info!(
+                        running_duration_ms = tracked_job.running_duration().as_millis() as u64,
+                        total_duration_ms = tracked_job.total_duration().as_millis() as u64,
+                        queued_duration_ms = tracked_job.queued_duration().as_millis() as u64,
+                        execution_duration_ms = tracked_job
+                            .execution_duration()
+                            .map(|duration| duration.as_millis() as u64),
+                        exit_code = tracked_job.exit_code,
+                        "job ended"
+                    );
+                });
+                tracked_job.span = Span::none();
+            }
             return Ok(());
         }
         bail!("Invalid job id");
     }
 
+    /// Record the exit code and/or result reported by a job. `None` values leave any previously
+    /// reported value unchanged.
+    pub fn set_result(
+        &mut self,
+        id: &str,
+        exit_code: Option<i64>,
+        result: Option<String>,
+    ) -> Result<()> {
+        let Some(tracked_job) = self.jobs.get(id) else {
+            bail!("Invalid job id");
+        };
+        let mut tracked_job = tracked_job.lock().unwrap();
+        if exit_code.is_some() {
+            tracked_job.exit_code = exit_code;
+        }
+        if result.is_some() {
+            tracked_job.result = result;
+        }
+        Ok(())
+    }
+
+    /// Record why a job failed, ahead of it becoming `Failed`.
+    pub fn set_failure_reason(&mut self, id: &str, reason: FailureReason) -> Result<()> {
+        let Some(tracked_job) = self.jobs.get(id) else {
+            bail!("Invalid job id");
+        };
+        tracked_job.lock().unwrap().failure_reason = Some(reason);
+        Ok(())
+    }
+
+    /// Record when the job's container started.
+    pub fn set_container_started(&mut self, id: &str, time: SystemTime) -> Result<()> {
+        let Some(tracked_job) = self.jobs.get(id) else {
+            bail!("Invalid job id");
+        };
+        tracked_job.lock().unwrap().container_started_time = Some(time);
+        Ok(())
+    }
+
     /// Returns a `Vec<String>` containing the IDs of jobs matching status
     fn get_job_ids_by_status(&self, job_status: JobStatus) -> Vec<String> {
         self.jobs
@@ -141,8 +590,8 @@ impl JobTracker {
     /// Count jobs matching status
     pub fn count_jobs_by_status(&self, job_status: JobStatus) -> usize {
         self.jobs
-            .iter()
-            .filter_map(|(_, tracked_job)| {
+            .values()
+            .filter_map(|tracked_job| {
                 tracked_job.lock().ok().and_then(|locked_job| {
                     if locked_job.status == job_status {
                         Some(())
@@ -154,14 +603,30 @@ impl JobTracker {
             .count()
     }
 
+    /// Returns a `Vec<String>` containing the IDs of all pending jobs.
+    pub fn get_pending_job_ids(&self) -> Vec<String> {
+        self.get_job_ids_by_status(JobStatus::Pending)
+    }
+
     /// Returns a `Vec<String>` containing the IDs of all completed jobs.
     pub fn get_completed_job_ids(&self) -> Vec<String> {
         self.get_job_ids_by_status(JobStatus::Completed)
     }
 
-    /// Returns a `Vec<String>` containing the IDs of all running jobs.
+    /// Returns a `Vec<String>` containing the IDs of all running jobs, ordered by when each job was
+    /// received, oldest first.
     pub fn get_running_job_ids(&self) -> Vec<String> {
-        self.get_job_ids_by_status(JobStatus::Running)
+        let mut jobs: Vec<(SystemTime, String)> = self
+            .jobs
+            .iter()
+            .filter_map(|(id, tracked_job)| {
+                let locked_job = tracked_job.lock().ok()?;
+                (locked_job.status == JobStatus::Running)
+                    .then(|| (locked_job.start_time, id.clone()))
+            })
+            .collect();
+        jobs.sort();
+        jobs.into_iter().map(|(_, id)| id).collect()
     }
 
     /// Returns a `Vec<String>` containing the IDs of all stopped jobs.
@@ -169,18 +634,20 @@ impl JobTracker {
         self.get_job_ids_by_status(JobStatus::Stopped)
     }
 
-    /// Returns a `Vec<String>` containing the IDs of any running jobs which have timed out.
-    pub fn get_timed_out_job_ids(&self) -> Vec<String> {
+    /// Returns a `Vec<String>` containing the IDs of any running jobs which have timed out,
+    /// measured from when each job transitioned to `Running`.
+    pub fn get_timed_out_job_ids(&self, default_timeout: Duration) -> Vec<String> {
         let now = SystemTime::now();
-        let job_completion_timeout = Duration::from_millis(SETTINGS.core.job_completion_timeout);
 
         self.jobs
             .iter()
             .filter_map(|(id, tracked_job)| {
                 tracked_job.lock().ok().and_then(|locked_job| {
-                    let elapsed = now.duration_since(locked_job.start_time).ok()?;
+                    // Measure from when the job started running so time spent pending isn't counted
+                    let elapsed = now.duration_since(locked_job.running_time?).ok()?;
 
-                    if locked_job.status == JobStatus::Running && elapsed > job_completion_timeout {
+                    let timeout = locked_job.timeout.unwrap_or(default_timeout);
+                    if locked_job.status == JobStatus::Running && elapsed > timeout {
                         Some(id.clone())
                     } else {
                         None
@@ -216,15 +683,117 @@ impl JobTracker {
             .collect()
     }
 
+    /// Returns a `Vec<String>` containing the IDs of all pending jobs which have been pending
+    /// for longer than `timeout`.
+    pub fn get_pending_and_expired_job_ids(&self, timeout: Duration) -> Vec<String> {
+        let now = SystemTime::now();
+
+        self.jobs
+            .iter()
+            .filter_map(|(id, tracked_job)| {
+                tracked_job.lock().ok().and_then(|locked_job| {
+                    if locked_job.status != JobStatus::Pending {
+                        return None;
+                    }
+
+                    let elapsed = now.duration_since(locked_job.start_time).ok()?;
+                    if elapsed > timeout {
+                        Some(id.clone())
+                    } else {
+                        None
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Returns a `Vec<String>` containing the IDs of all pending or running jobs which were
+    /// inserted longer than `max_lifetime` ago.
+    pub fn get_lifetime_exceeded_job_ids(&self, max_lifetime: Duration) -> Vec<String> {
+        let now = SystemTime::now();
+
+        self.jobs
+            .iter()
+            .filter_map(|(id, tracked_job)| {
+                tracked_job.lock().ok().and_then(|locked_job| {
+                    if !matches!(locked_job.status, JobStatus::Pending | JobStatus::Running) {
+                        return None;
+                    }
+
+                    let elapsed = now.duration_since(locked_job.start_time).ok()?;
+                    if elapsed > max_lifetime {
+                        Some(id.clone())
+                    } else {
+                        None
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Returns a `JobIdBuckets` containing the IDs of completed, timed-out, stopped-and-expired,
+    /// pending-and-expired, lifetime-exceeded, running and stopped jobs.
+    ///
+    /// A job which has exceeded it's maximum lifetime is only included in `lifetime_exceeded` if it
+    /// is not already in `timed_out` or `pending_and_expired`, so it is only stopped once.
+    pub fn get_job_id_buckets(&self) -> JobIdBuckets {
+        let timed_out =
+            self.get_timed_out_job_ids(Duration::from_millis(SETTINGS.core.job_completion_timeout));
+        let pending_and_expired = self.get_pending_and_expired_job_ids(Duration::from_millis(
+            SETTINGS.core.job_start_timeout,
+        ));
+        let lifetime_exceeded = match SETTINGS.core.max_job_lifetime {
+            Some(max_job_lifetime) => self
+                .get_lifetime_exceeded_job_ids(Duration::from_millis(max_job_lifetime))
+                .into_iter()
+                .filter(|id| !timed_out.contains(id) && !pending_and_expired.contains(id))
+                .collect(),
+            None => Vec::new(),
+        };
+        JobIdBuckets {
+            pending: self.get_pending_job_ids(),
+            completed: self.get_completed_job_ids(),
+            timed_out,
+            stopped_and_expired: self.get_stopped_and_expired_job_ids(),
+            pending_and_expired,
+            lifetime_exceeded,
+            running: self.get_running_job_ids(),
+            stopped: self.get_stopped_job_ids(),
+        }
+    }
+
     /// Count running jobs
     pub fn count_running_jobs(&self) -> usize {
         self.count_jobs_by_status(JobStatus::Running)
     }
+
+    /// Stop tracking jobs which reached a terminal status (`Finished` or `Failed`) longer than
+    /// `retention` ago. Returns the IDs of the removed jobs.
+    pub fn remove_finished_jobs(&mut self, retention: Duration) -> Vec<String> {
+        let now = SystemTime::now();
+        let mut removed = Vec::new();
+        self.jobs.retain(|id, tracked_job| {
+            let expired = tracked_job.lock().is_ok_and(|locked_job| {
+                locked_job.status.is_terminal()
+                    && locked_job
+                        .finished_time
+                        .and_then(|finished_time| now.duration_since(finished_time).ok())
+                        .is_some_and(|elapsed| elapsed > retention)
+            });
+            if expired {
+                removed.push(id.clone());
+            }
+            !expired
+        });
+        removed
+    }
 }
 
 pub enum JobTrackerCommand {
     Insert {
-        job: Job,
+        job: Box<Job>,
+        span: Span,
+        resp: JobTrackerCommandResponder<bool>,
     },
     GetJob {
         job_id: String,
@@ -236,45 +805,78 @@ pub enum JobTrackerCommand {
         progress: Option<f64>,
         resp: JobTrackerCommandResponder<()>,
     },
-    GetRunningJobIds {
-        resp: JobTrackerCommandResponder<Vec<String>>,
-    },
-    GetStoppedJobIds {
-        resp: JobTrackerCommandResponder<Vec<String>>,
+    SetResult {
+        job_id: String,
+        exit_code: Option<i64>,
+        result: Option<String>,
+        resp: JobTrackerCommandResponder<()>,
     },
-    GetTimedOutJobIds {
-        resp: JobTrackerCommandResponder<Vec<String>>,
+    SetFailureReason {
+        job_id: String,
+        reason: FailureReason,
+        resp: JobTrackerCommandResponder<()>,
     },
-    GetCompletedJobIds {
-        resp: JobTrackerCommandResponder<Vec<String>>,
+    SetContainerStarted {
+        job_id: String,
+        time: SystemTime,
+        resp: JobTrackerCommandResponder<()>,
     },
-    GetStoppedAndExpiredJobIds {
-        resp: JobTrackerCommandResponder<Vec<String>>,
+    GetJobIdBuckets {
+        resp: JobTrackerCommandResponder<JobIdBuckets>,
     },
     CountRunningJobs {
         resp: JobTrackerCommandResponder<usize>,
     },
+    RemoveFinishedJobs {
+        retention: Duration,
+        resp: JobTrackerCommandResponder<Vec<String>>,
+    },
 }
 
 pub type JobTrackerCommandResponder<T> = oneshot::Sender<Result<T>>;
 
+/// How long to wait for the job tracker to accept a command, and then to respond to it, before
+/// giving up.
+const TRACKER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Send a command to the job tracker task, returning it's response. Errors if the tracker is too
+/// busy to accept or respond to the command or is no longer running, rather than panicking.
+async fn send_command<T>(
+    tx: &Sender<JobTrackerCommand>,
+    command: impl FnOnce(JobTrackerCommandResponder<T>) -> JobTrackerCommand,
+) -> Result<T> {
+    let (resp_tx, resp_rx) = oneshot::channel();
+    match tx.send_timeout(command(resp_tx), TRACKER_TIMEOUT).await {
+        Err(SendTimeoutError::Timeout(_)) => bail!("Job tracker is busy"),
+        Err(SendTimeoutError::Closed(_)) => bail!("Job tracker is not running"),
+        _ => {}
+    }
+    match tokio::time::timeout(TRACKER_TIMEOUT, resp_rx).await {
+        std::result::Result::Ok(std::result::Result::Ok(result)) => result,
+        std::result::Result::Ok(Err(_)) => bail!("Job tracker dropped the command"),
+        Err(_) => bail!("Timed out waiting for the job tracker to respond"),
+    }
+}
+
+/// Track a new job, returning `true` if the job was not already tracked.
+pub async fn insert_job(job: Job, span: Span, tx: &Sender<JobTrackerCommand>) -> Result<bool> {
+    send_command(tx, |resp| JobTrackerCommand::Insert {
+        job: Box::new(job),
+        span,
+        resp,
+    })
+    .await
+}
+
 pub async fn get_job(
     job_id: &str,
     tx: &Sender<JobTrackerCommand>,
-) -> Option<Arc<Mutex<TrackedJob>>> {
-    let (resp_tx, resp_rx) = oneshot::channel();
-    tx.send(JobTrackerCommand::GetJob {
+) -> Result<Option<Arc<Mutex<TrackedJob>>>> {
+    send_command(tx, |resp| JobTrackerCommand::GetJob {
         job_id: job_id.to_owned(),
-        resp: resp_tx,
+        resp,
     })
     .await
-    .expect("Failed sending GetJob command");
-
-    resp_rx
-        .await
-        .expect("Failed to get job from channel")
-        .ok()
-        .flatten()
 }
 
 pub async fn update_job_status(
@@ -283,72 +885,79 @@ pub async fn update_job_status(
     progress: Option<f64>,
     tx: &Sender<JobTrackerCommand>,
 ) -> Result<()> {
-    let (resp_tx, resp_rx) = oneshot::channel();
-    tx.send(JobTrackerCommand::UpdateStatus {
+    send_command(tx, |resp| JobTrackerCommand::UpdateStatus {
         job_id: job_id.to_owned(),
         status,
         progress,
-        resp: resp_tx,
+        resp,
     })
     .await
-    .expect("Failed sending UpdateStatus command");
-
-    if let Err(e) = resp_rx.await {
-        bail!("Error updating job status: {}", e);
-    };
-    Ok(())
 }
 
-async fn get_job_ids_helper(
+/// Record the exit code and/or result reported by a job.
+pub async fn set_job_result(
+    job_id: &str,
+    exit_code: Option<i64>,
+    result: Option<String>,
     tx: &Sender<JobTrackerCommand>,
-    command_factory: impl FnOnce(oneshot::Sender<Result<Vec<String>>>) -> JobTrackerCommand,
-) -> Option<Vec<String>> {
-    let (resp_tx, resp_rx) = oneshot::channel();
-    tx.send(command_factory(resp_tx))
-        .await
-        .expect("Failed sending command to job tracker");
-
-    resp_rx
-        .await
-        .expect("Failed getting job ids from channel")
-        .ok()
+) -> Result<()> {
+    send_command(tx, |resp| JobTrackerCommand::SetResult {
+        job_id: job_id.to_owned(),
+        exit_code,
+        result,
+        resp,
+    })
+    .await
 }
 
-pub async fn get_timed_out_job_ids(tx: &Sender<JobTrackerCommand>) -> Option<Vec<String>> {
-    get_job_ids_helper(tx, |resp| JobTrackerCommand::GetTimedOutJobIds { resp }).await
+/// Record why a job failed.
+pub async fn set_failure_reason(
+    job_id: &str,
+    reason: FailureReason,
+    tx: &Sender<JobTrackerCommand>,
+) -> Result<()> {
+    send_command(tx, |resp| JobTrackerCommand::SetFailureReason {
+        job_id: job_id.to_owned(),
+        reason,
+        resp,
+    })
+    .await
 }
 
-pub async fn get_running_job_ids(tx: &Sender<JobTrackerCommand>) -> Option<Vec<String>> {
-    get_job_ids_helper(tx, |resp| JobTrackerCommand::GetRunningJobIds { resp }).await
+/// Record when a job's container started.
+pub async fn set_container_started(
+    job_id: &str,
+    time: SystemTime,
+    tx: &Sender<JobTrackerCommand>,
+) -> Result<()> {
+    send_command(tx, |resp| JobTrackerCommand::SetContainerStarted {
+        job_id: job_id.to_owned(),
+        time,
+        resp,
+    })
+    .await
 }
 
-pub async fn get_stopped_job_ids(tx: &Sender<JobTrackerCommand>) -> Option<Vec<String>> {
-    get_job_ids_helper(tx, |resp| JobTrackerCommand::GetStoppedJobIds { resp }).await
+pub async fn get_job_id_buckets(tx: &Sender<JobTrackerCommand>) -> Result<JobIdBuckets> {
+    send_command(tx, |resp| JobTrackerCommand::GetJobIdBuckets { resp }).await
 }
 
-pub async fn get_completed_job_ids(tx: &Sender<JobTrackerCommand>) -> Option<Vec<String>> {
-    get_job_ids_helper(tx, |resp| JobTrackerCommand::GetCompletedJobIds { resp }).await
+pub async fn count_running_jobs(tx: &Sender<JobTrackerCommand>) -> Result<usize> {
+    send_command(tx, |resp| JobTrackerCommand::CountRunningJobs { resp }).await
 }
 
-pub async fn get_stopped_and_expired_job_ids(
+/// Stop tracking jobs which finished longer than `retention` ago, returning their IDs.
+pub async fn remove_finished_jobs(
+    retention: Duration,
     tx: &Sender<JobTrackerCommand>,
-) -> Option<Vec<String>> {
-    get_job_ids_helper(tx, |resp| JobTrackerCommand::GetStoppedAndExpiredJobIds {
+) -> Result<Vec<String>> {
+    send_command(tx, |resp| JobTrackerCommand::RemoveFinishedJobs {
+        retention,
         resp,
     })
     .await
 }
 
-pub async fn count_running_jobs(tx: &Sender<JobTrackerCommand>) -> Result<usize> {
-    let (resp_tx, resp_rx) = oneshot::channel();
-    tx.send(JobTrackerCommand::CountRunningJobs { resp: resp_tx })
-        .await
-        .expect("Failed sending count running jobs command");
-    resp_rx
-        .await
-        .expect("Failed getting count running jobs response")
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -363,5 +972,490 @@ mod tests {
 
         let j: JobStatus = "completed".parse().expect("Failed to parse job status");
         assert_eq!(j, JobStatus::Completed);
+
+        let j: JobStatus = "failed".parse().expect("Failed to parse job status");
+        assert_eq!(j, JobStatus::Failed);
+    }
+
+    fn test_job(id: &str) -> Job {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "image": "alpine:latest",
+            "body": null,
+            "callbackUrl": "https://api.example.com/callback"
+        }))
+        .unwrap()
+    }
+
+    /// Insert a job into the tracker, backdating it's start time by `age`.
+    fn insert_aged_job(job_tracker: &mut JobTracker, id: &str, age: Duration) {
+        job_tracker.insert(test_job(id), Span::none()).unwrap();
+        let mut tracked_job = job_tracker.get_job(id).unwrap().lock().unwrap();
+        tracked_job.start_time = SystemTime::now() - age;
+    }
+
+    #[test]
+    fn test_running_job_ids_ordered_by_start_time() {
+        let mut job_tracker = JobTracker::new();
+        insert_aged_job(&mut job_tracker, "newest", Duration::from_secs(1));
+        insert_aged_job(&mut job_tracker, "oldest", Duration::from_secs(30));
+        insert_aged_job(&mut job_tracker, "middle", Duration::from_secs(10));
+        insert_aged_job(&mut job_tracker, "pending", Duration::from_secs(60));
+        for id in ["newest", "oldest", "middle"] {
+            job_tracker
+                .update_status(id, JobStatus::Running, None)
+                .unwrap();
+        }
+        assert_eq!(
+            job_tracker.get_running_job_ids(),
+            vec!["oldest", "middle", "newest"]
+        );
+    }
+
+    #[test]
+    fn test_record_update_rate_limited() {
+        let mut job_tracker = JobTracker::new();
+        job_tracker
+            .insert(test_job("123abc"), Span::none())
+            .unwrap();
+        let mut tracked_job = job_tracker.get_job("123abc").unwrap().lock().unwrap();
+        let start = Instant::now();
+        assert!(tracked_job.record_update(2, start));
+        assert!(tracked_job.record_update(2, start + Duration::from_millis(100)));
+        assert!(!tracked_job.record_update(2, start + Duration::from_millis(200)));
+        // The first update falls out of the window after a second
+        assert!(tracked_job.record_update(2, start + Duration::from_millis(1000)));
+        assert!(!tracked_job.record_update(2, start + Duration::from_millis(1050)));
+    }
+
+    #[test]
+    fn test_job_ids_ordered_by_id() {
+        let mut job_tracker = JobTracker::new();
+        for id in ["c", "a", "d", "b"] {
+            job_tracker.insert(test_job(id), Span::none()).unwrap();
+        }
+        for id in ["d", "b"] {
+            job_tracker
+                .update_status(id, JobStatus::Stopped, None)
+                .unwrap();
+        }
+        assert_eq!(job_tracker.get_pending_job_ids(), vec!["a", "c"]);
+        assert_eq!(job_tracker.get_stopped_job_ids(), vec!["b", "d"]);
+    }
+
+    #[tokio::test]
+    async fn test_slot_freed_when_running_job_completes() {
+        let mut job_tracker = JobTracker::new();
+        let slot_freed = job_tracker.slot_freed();
+        insert_aged_job(&mut job_tracker, "123abc", Duration::ZERO);
+        job_tracker
+            .update_status("123abc", JobStatus::Running, None)
+            .unwrap();
+        job_tracker
+            .update_status("123abc", JobStatus::Completed, None)
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(1), slot_freed.notified())
+            .await
+            .expect("Expected slot freed notification");
+    }
+
+    #[tokio::test]
+    async fn test_commands_error_when_tracker_not_running() {
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        drop(rx);
+        assert!(get_job("123abc", &tx).await.is_err());
+        assert!(update_job_status("123abc", JobStatus::Running, None, &tx)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_coalesce_updates() {
+        let updates: Vec<JobUpdate> = serde_json::from_str(
+            r#"[
+                { "status": "RUNNING", "progress": 0.5 },
+                { "status": "RUNNING", "progress": 0.9, "exitCode": 0, "result": "ok" },
+                { "status": "COMPLETED", "progress": 1.0 }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            coalesce_updates(updates, OutOfRangeProgress::Reject).unwrap(),
+            JobUpdate {
+                status: JobStatus::Completed,
+                progress: 1.0,
+                exit_code: Some(0),
+                result: Some("ok".to_owned()),
+            }
+        );
+        assert!(coalesce_updates(vec![], OutOfRangeProgress::Reject).is_err());
+        let invalid = || -> Vec<JobUpdate> {
+            serde_json::from_str(r#"[{ "status": "RUNNING", "progress": 1.5 }]"#).unwrap()
+        };
+        assert!(coalesce_updates(invalid(), OutOfRangeProgress::Reject).is_err());
+        assert_eq!(
+            coalesce_updates(invalid(), OutOfRangeProgress::Clamp)
+                .unwrap()
+                .progress,
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_insert_rejects_invalid_and_duplicate_ids() {
+        let mut job_tracker = JobTracker::new();
+        assert!(job_tracker
+            .insert(test_job("123abc"), Span::none())
+            .unwrap());
+        job_tracker
+            .update_status("123abc", JobStatus::Running, Some(0.5))
+            .unwrap();
+
+        // A duplicate is ignored and the existing job's state is preserved
+        assert!(!job_tracker
+            .insert(test_job("123abc"), Span::none())
+            .unwrap());
+        let tracked_job = job_tracker.get_job("123abc").unwrap().lock().unwrap();
+        assert_eq!(*tracked_job.status(), JobStatus::Running);
+        assert_eq!(tracked_job.progress(), 0.5);
+        drop(tracked_job);
+
+        assert!(job_tracker
+            .insert(test_job("123/abc"), Span::none())
+            .is_err());
+        assert!(job_tracker.get_job("123/abc").is_none());
+    }
+
+    #[test]
+    fn test_events_are_broadcast() {
+        let mut job_tracker = JobTracker::new();
+        let mut rx = job_tracker.events().subscribe();
+        insert_aged_job(&mut job_tracker, "123abc", Duration::ZERO);
+        job_tracker
+            .update_status("123abc", JobStatus::Running, Some(0.5))
+            .unwrap();
+        job_tracker
+            .update_status("123abc", JobStatus::Finished, None)
+            .unwrap();
+
+        let event = rx.try_recv().expect("Expected an inserted event");
+        assert_eq!(event.kind, JobEventKind::Inserted);
+        assert_eq!(event.job_id, "123abc");
+        assert_eq!(event.old_status, None);
+        assert_eq!(event.status, JobStatus::Pending);
+
+        let event = rx.try_recv().expect("Expected a status changed event");
+        assert_eq!(event.kind, JobEventKind::StatusChanged);
+        assert_eq!(event.old_status, Some(JobStatus::Pending));
+        assert_eq!(event.status, JobStatus::Running);
+        assert_eq!(event.progress, 0.5);
+
+        let event = rx.try_recv().expect("Expected a removed event");
+        assert_eq!(event.kind, JobEventKind::Removed);
+        assert_eq!(event.old_status, Some(JobStatus::Running));
+        assert_eq!(event.status, JobStatus::Finished);
+
+        // Late subscribers don't see earlier events
+        let mut late_rx = job_tracker.events().subscribe();
+        assert!(late_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_durations() {
+        let mut job_tracker = JobTracker::new();
+        insert_aged_job(&mut job_tracker, "123abc", Duration::from_secs(60));
+        job_tracker
+            .update_status("123abc", JobStatus::Running, None)
+            .unwrap();
+        {
+            let tracked_job = job_tracker.get_job("123abc").unwrap().lock().unwrap();
+            assert!(tracked_job.running_duration() >= Duration::from_secs(60));
+            assert!(tracked_job.total_duration() >= Duration::from_secs(60));
+        }
+
+        // Durations are fixed once the job has stopped/finished
+        job_tracker
+            .update_status("123abc", JobStatus::Stopped, None)
+            .unwrap();
+        job_tracker
+            .update_status("123abc", JobStatus::Finished, None)
+            .unwrap();
+        let mut tracked_job = job_tracker.get_job("123abc").unwrap().lock().unwrap();
+        tracked_job.stopped_time = Some(tracked_job.start_time + Duration::from_secs(30));
+        tracked_job.finished_time = Some(tracked_job.start_time + Duration::from_secs(45));
+        assert_eq!(tracked_job.running_duration(), Duration::from_secs(30));
+        assert_eq!(tracked_job.total_duration(), Duration::from_secs(45));
+    }
+
+    #[test]
+    fn test_queued_and_execution_durations() {
+        let mut job_tracker = JobTracker::new();
+        insert_aged_job(&mut job_tracker, "123abc", Duration::from_secs(60));
+        {
+            let tracked_job = job_tracker.get_job("123abc").unwrap().lock().unwrap();
+            assert!(tracked_job.queued_duration() >= Duration::from_secs(60));
+            assert_eq!(tracked_job.execution_duration(), None);
+        }
+
+        let start_time = job_tracker
+            .get_job("123abc")
+            .unwrap()
+            .lock()
+            .unwrap()
+            .start_time;
+        job_tracker
+            .set_container_started("123abc", start_time + Duration::from_secs(20))
+            .unwrap();
+        job_tracker
+            .update_status("123abc", JobStatus::Stopped, None)
+            .unwrap();
+        let mut tracked_job = job_tracker.get_job("123abc").unwrap().lock().unwrap();
+        tracked_job.stopped_time = Some(start_time + Duration::from_secs(50));
+        assert_eq!(tracked_job.queued_duration(), Duration::from_secs(20));
+        assert_eq!(
+            tracked_job.execution_duration(),
+            Some(Duration::from_secs(30))
+        );
+        drop(tracked_job);
+        assert!(job_tracker
+            .set_container_started("unknown", SystemTime::now())
+            .is_err());
+    }
+
+    #[test]
+    fn test_get_pending_and_expired_job_ids() {
+        let mut job_tracker = JobTracker::new();
+        insert_aged_job(&mut job_tracker, "old-pending", Duration::from_secs(120));
+        insert_aged_job(&mut job_tracker, "old-running", Duration::from_secs(120));
+        insert_aged_job(&mut job_tracker, "young-pending", Duration::from_secs(1));
+        job_tracker
+            .update_status("old-running", JobStatus::Running, None)
+            .unwrap();
+
+        let ids = job_tracker.get_pending_and_expired_job_ids(Duration::from_secs(60));
+        assert_eq!(ids, vec!["old-pending"]);
+
+        // Once failed the job is no longer pending so is not returned again
+        job_tracker
+            .update_status("old-pending", JobStatus::Failed, None)
+            .unwrap();
+        let ids = job_tracker.get_pending_and_expired_job_ids(Duration::from_secs(60));
+        assert!(ids.is_empty());
+    }
+
+    #[test]
+    fn test_get_timed_out_job_ids_with_per_job_timeout() {
+        let mut job_tracker = JobTracker::new();
+        let mut job = test_job("long-timeout");
+        let Job::Docker(ref mut docker_job) = job;
+        docker_job.timeout = Some(300_000);
+        job_tracker.insert(job, Span::none()).unwrap();
+        insert_aged_job(&mut job_tracker, "default-timeout", Duration::ZERO);
+        for id in ["long-timeout", "default-timeout"] {
+            job_tracker
+                .update_status(id, JobStatus::Running, None)
+                .unwrap();
+            let mut tracked_job = job_tracker.get_job(id).unwrap().lock().unwrap();
+            tracked_job.running_time = Some(SystemTime::now() - Duration::from_secs(120));
+        }
+
+        let ids = job_tracker.get_timed_out_job_ids(Duration::from_secs(60));
+
+        assert_eq!(ids, vec!["default-timeout"]);
+    }
+
+    #[test]
+    fn test_get_timed_out_job_ids_ignores_time_pending() {
+        let mut job_tracker = JobTracker::new();
+        insert_aged_job(&mut job_tracker, "123abc", Duration::from_secs(120));
+        job_tracker
+            .update_status("123abc", JobStatus::Running, None)
+            .unwrap();
+
+        assert!(job_tracker
+            .get_timed_out_job_ids(Duration::from_secs(60))
+            .is_empty());
+
+        // Progress updates don't reset the running time
+        let running_time = Some(SystemTime::now() - Duration::from_secs(61));
+        job_tracker
+            .get_job("123abc")
+            .unwrap()
+            .lock()
+            .unwrap()
+            .running_time = running_time;
+        job_tracker
+            .update_status("123abc", JobStatus::Running, Some(0.5))
+            .unwrap();
+
+        assert_eq!(
+            job_tracker.get_timed_out_job_ids(Duration::from_secs(60)),
+            vec!["123abc"]
+        );
+    }
+
+    #[test]
+    fn test_get_lifetime_exceeded_job_ids() {
+        let mut job_tracker = JobTracker::new();
+        insert_aged_job(&mut job_tracker, "old-pending", Duration::from_secs(120));
+        insert_aged_job(&mut job_tracker, "old-running", Duration::from_secs(120));
+        insert_aged_job(&mut job_tracker, "old-stopped", Duration::from_secs(120));
+        insert_aged_job(&mut job_tracker, "young", Duration::from_secs(1));
+        job_tracker
+            .update_status("old-running", JobStatus::Running, None)
+            .unwrap();
+        job_tracker
+            .update_status("old-stopped", JobStatus::Stopped, None)
+            .unwrap();
+
+        assert_eq!(
+            job_tracker.get_lifetime_exceeded_job_ids(Duration::from_secs(60)),
+            vec!["old-pending", "old-running"]
+        );
+    }
+
+    #[test]
+    fn test_remove_finished_jobs() {
+        let mut job_tracker = JobTracker::new();
+        for id in ["old-finished", "old-failed", "new-finished", "running"] {
+            job_tracker.insert(test_job(id), Span::none()).unwrap();
+        }
+        job_tracker
+            .update_status("old-finished", JobStatus::Finished, None)
+            .unwrap();
+        job_tracker
+            .update_status("old-failed", JobStatus::Failed, None)
+            .unwrap();
+        job_tracker
+            .update_status("new-finished", JobStatus::Finished, None)
+            .unwrap();
+        job_tracker
+            .update_status("running", JobStatus::Running, None)
+            .unwrap();
+        for id in ["old-finished", "old-failed"] {
+            let mut tracked_job = job_tracker.get_job(id).unwrap().lock().unwrap();
+            tracked_job.finished_time = Some(SystemTime::now() - Duration::from_secs(120));
+        }
+
+        assert_eq!(
+            job_tracker.remove_finished_jobs(Duration::from_secs(60)),
+            vec!["old-failed", "old-finished"]
+        );
+        assert!(job_tracker.get_job("old-finished").is_none());
+        assert!(job_tracker.get_job("old-failed").is_none());
+        assert!(job_tracker.get_job("new-finished").is_some());
+        assert!(job_tracker.get_job("running").is_some());
+    }
+
+    #[test]
+    fn test_set_result() {
+        let mut job_tracker = JobTracker::new();
+        job_tracker
+            .insert(test_job("123abc"), Span::none())
+            .unwrap();
+
+        job_tracker
+            .set_result("123abc", Some(1), Some("partial".to_string()))
+            .unwrap();
+        job_tracker.set_result("123abc", None, None).unwrap();
+
+        let tracked_job = job_tracker.get_job("123abc").unwrap().lock().unwrap();
+        assert_eq!(tracked_job.exit_code(), Some(1));
+        assert_eq!(tracked_job.result(), Some("partial"));
+        drop(tracked_job);
+        assert!(job_tracker.set_result("unknown", Some(0), None).is_err());
+    }
+
+    #[test]
+    fn test_job_status_is_fetchable() {
+        assert!(JobStatus::Pending.is_fetchable());
+        assert!(JobStatus::Running.is_fetchable());
+        assert!(!JobStatus::Completed.is_fetchable());
+        assert!(!JobStatus::Stopped.is_fetchable());
+        assert!(!JobStatus::Finished.is_fetchable());
+        assert!(!JobStatus::Failed.is_fetchable());
+    }
+
+    #[test]
+    fn test_completed_job_is_not_fetchable() {
+        let mut job_tracker = JobTracker::new();
+        job_tracker
+            .insert(test_job("123abc"), Span::none())
+            .unwrap();
+        let is_fetchable = |job_tracker: &JobTracker| {
+            let tracked_job = job_tracker.get_job("123abc").unwrap().lock().unwrap();
+            tracked_job.status().is_fetchable()
+        };
+        assert!(is_fetchable(&job_tracker));
+
+        job_tracker
+            .update_status("123abc", JobStatus::Running, Some(0.5))
+            .unwrap();
+        assert!(is_fetchable(&job_tracker));
+
+        job_tracker
+            .update_status("123abc", JobStatus::Completed, Some(1.0))
+            .unwrap();
+        assert!(!is_fetchable(&job_tracker));
+        assert_eq!(job_tracker.get_completed_job_ids(), vec!["123abc"]);
+
+        // The lifecycle task then stops the container, so it's no longer completed
+        job_tracker
+            .update_status("123abc", JobStatus::Stopped, None)
+            .unwrap();
+        assert!(!is_fetchable(&job_tracker));
+        assert!(job_tracker.get_completed_job_ids().is_empty());
+        assert_eq!(job_tracker.get_stopped_job_ids(), vec!["123abc"]);
+    }
+
+    #[test]
+    fn test_validate_progress() {
+        assert!(validate_progress(0.0).is_ok());
+        assert!(validate_progress(0.5).is_ok());
+        assert!(validate_progress(1.0).is_ok());
+        assert!(validate_progress(-0.1).is_err());
+        assert!(validate_progress(1.1).is_err());
+        assert!(validate_progress(50.0).is_err());
+        assert!(validate_progress(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_normalize_progress() {
+        assert_eq!(
+            normalize_progress(0.5, OutOfRangeProgress::Reject).unwrap(),
+            0.5
+        );
+        assert!(normalize_progress(1.5, OutOfRangeProgress::Reject).is_err());
+        assert_eq!(
+            normalize_progress(0.5, OutOfRangeProgress::Clamp).unwrap(),
+            0.5
+        );
+        assert_eq!(
+            normalize_progress(-0.1, OutOfRangeProgress::Clamp).unwrap(),
+            0.0
+        );
+        assert_eq!(
+            normalize_progress(50.0, OutOfRangeProgress::Clamp).unwrap(),
+            1.0
+        );
+        assert!(normalize_progress(f64::NAN, OutOfRangeProgress::Clamp).is_err());
+    }
+
+    #[test]
+    fn test_update_status_rejects_invalid_progress() {
+        let mut job_tracker = JobTracker::new();
+        job_tracker
+            .insert(test_job("123abc"), Span::none())
+            .unwrap();
+        job_tracker
+            .update_status("123abc", JobStatus::Running, Some(0.5))
+            .unwrap();
+
+        assert!(job_tracker
+            .update_status("123abc", JobStatus::Running, Some(1.5))
+            .is_err());
+        let tracked_job = job_tracker.get_job("123abc").unwrap().lock().unwrap();
+        assert_eq!(tracked_job.progress(), 0.5);
     }
 }